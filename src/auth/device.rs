@@ -0,0 +1,87 @@
+use super::{ErrorResponse, Notice, Token};
+use crate::error::Error;
+use anyhow::Result;
+use reqwest::Client;
+use serde::Deserialize;
+use std::time::Duration;
+
+const GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+#[derive(Deserialize, Debug)]
+struct DeviceCode {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// Runs the OAuth device code grant: the user confirms a code on twitch.tv while we poll
+/// the token endpoint until they did.
+pub async fn login(
+    client: &Client,
+    auth_url: &str,
+    client_id: &str,
+    scopes: &[String],
+    notify: &dyn Fn(Notice),
+) -> Result<Token> {
+    let scopes = scopes.join(" ");
+    let res = client
+        .post(format!("{auth_url}/device"))
+        .form(&[("client_id", client_id), ("scopes", &scopes)])
+        .send()
+        .await
+        .map_err(super::network)?;
+    if !res.status().is_success() {
+        return Err(super::error_from(res).await.into());
+    }
+    let code: DeviceCode = super::json(res).await?;
+
+    notify(Notice::EnterCode {
+        uri: &code.verification_uri,
+        code: &code.user_code,
+    });
+
+    let mut interval = Duration::from_secs(code.interval.max(1));
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(code.expires_in);
+    loop {
+        tokio::time::sleep(interval).await;
+        if tokio::time::Instant::now() > deadline {
+            return Err(Error::Unauthorized(String::from(
+                "The device code expired before it was confirmed, please try again",
+            ))
+            .into());
+        }
+        let res = client
+            .post(format!("{auth_url}/token"))
+            .form(&[
+                ("client_id", client_id),
+                ("scopes", &scopes),
+                ("device_code", &code.device_code),
+                ("grant_type", GRANT_TYPE),
+            ])
+            .send()
+            .await
+            .map_err(super::network)?;
+        if res.status().is_success() {
+            return Ok(super::json(res).await?);
+        }
+        let status = res.status();
+        let message = res
+            .json::<ErrorResponse>()
+            .await
+            .map(|e| e.message)
+            .unwrap_or_default();
+        match message.as_str() {
+            "authorization_pending" => continue,
+            "slow_down" => interval += Duration::from_secs(5),
+            "access_denied" => {
+                return Err(Error::Unauthorized(String::from(
+                    "The authorization request was denied",
+                ))
+                .into())
+            }
+            _ => return Err(super::rejected(status, &message).into()),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+pub mod device;
+pub mod redirect;
+
+use crate::config::{self, AuthFlow, Config, Profile};
+use crate::error::Error;
+use crate::http;
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use reqwest::{header, Client, StatusCode};
+use serde::Deserialize;
+use tracing::debug;
+
+/// How the OAuth endpoints are named in errors.
+const SERVICE: &str = "twitch oauth";
+/// Twitch asks clients to validate their tokens at least once an hour.
+const VALIDATION_INTERVAL_MINUTES: i64 = 60;
+/// Tokens that expire within this window are refreshed ahead of time.
+const EXPIRY_MARGIN_SECONDS: i64 = 60;
+
+/// A token as returned by the `/token` endpoint.
+#[derive(Deserialize, Debug)]
+pub struct Token {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Vec<String>,
+}
+
+/// What the `/validate` endpoint knows about a token.
+#[derive(Deserialize, Debug)]
+pub struct Validation {
+    pub client_id: String,
+    pub login: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Seconds until the token expires, 0 for tokens that do not expire.
+    pub expires_in: i64,
+}
+
+/// Something a login needs the user to see, handed to the caller to show it.
+#[derive(Debug, Clone, Copy)]
+pub enum Notice<'a> {
+    /// The device flow waits for `code` to be entered at `uri`.
+    EnterCode { uri: &'a str, code: &'a str },
+    /// The redirect flow waits for the user to log in at `url`.
+    OpenUrl { url: &'a str },
+    /// A login finished, as `login`.
+    LoggedIn { login: &'a str },
+}
+
+#[derive(Deserialize, Debug)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// Logs in with the configured flow unless the config already holds a token.
+pub async fn ensure_login(config: &mut Config, notify: &dyn Fn(Notice)) -> Result<()> {
+    if config.is_logged_in() {
+        return Ok(());
+    }
+    login(config, notify).await?;
+    notify(Notice::LoggedIn {
+        login: &config.profile().login,
+    });
+    Ok(())
+}
+
+/// Makes sure the config holds a token that is valid and carries `scopes`, logging in,
+/// revalidating or refreshing it as needed.
+pub async fn ensure_valid(
+    config: &mut Config,
+    scopes: &[&str],
+    notify: &dyn Fn(Notice),
+) -> Result<()> {
+    ensure_login(config, notify).await?;
+    let client = http::client(config);
+    let now = Utc::now();
+
+    let expiring = config
+        .profile()
+        .expires_at
+        .is_some_and(|at| at - Duration::seconds(EXPIRY_MARGIN_SECONDS) <= now);
+    if expiring {
+        refresh_or_bail(config).await?;
+    }
+
+    let stale = config
+        .profile()
+        .validated_at
+        .is_none_or(|at| now - at >= Duration::minutes(VALIDATION_INTERVAL_MINUTES));
+    if stale {
+        match validate(
+            &client,
+            &config.auth.base_url(),
+            &config.profile().access_token,
+        )
+        .await?
+        {
+            Some(validation) => apply_validation(config.profile_mut(), validation),
+            None => {
+                refresh_or_bail(config).await?;
+            }
+        }
+        config.save()?;
+    }
+
+    let missing: Vec<&str> = scopes
+        .iter()
+        .copied()
+        .filter(|scope| !config.profile().scopes.iter().any(|s| s == scope))
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::Forbidden(format!(
+            "This command needs the {} scope(s), which your token was not granted.\n\
+             Add them to `scopes` in the [auth] section of the config and run `tl auth login`.",
+            missing.join(", ")
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Obtains a new token with the configured flow and stores it in the config. What the user has
+/// to do to log in goes to `notify`.
+pub async fn login(config: &mut Config, notify: &dyn Fn(Notice)) -> Result<()> {
+    if config.client_id.is_empty() {
+        config.client_id = config::prompt(
+            "Twitch application client ID (register one at https://dev.twitch.tv/console/apps)",
+        )?;
+    }
+    let client_id = config.client_id.clone();
+    config.profile_mut().client_id = client_id.clone();
+    let client = http::client(config);
+    let token = match config.auth.flow {
+        AuthFlow::Device => {
+            device::login(
+                &client,
+                &config.auth.base_url(),
+                &client_id,
+                &config.auth.scopes,
+                notify,
+            )
+            .await?
+        }
+        AuthFlow::Redirect => redirect::login(&client, &client_id, &config.auth, notify).await?,
+    };
+    store_token(&client, config, token).await
+}
+
+/// Stores a token obtained elsewhere (e.g. a CI secret) after checking it with Twitch.
+pub async fn login_with_token(config: &mut Config, access_token: &str) -> Result<()> {
+    let access_token = access_token.trim_start_matches("oauth:");
+    let client = http::client(config);
+    let validation = validate(&client, &config.auth.base_url(), access_token)
+        .await?
+        .ok_or_else(|| Error::Unauthorized(String::from("Twitch did not accept the token")))?;
+    let profile = config.profile_mut();
+    profile.client_id = validation.client_id.clone();
+    profile.access_token = access_token.to_string();
+    profile.refresh_token.clear();
+    apply_validation(profile, validation);
+    config.save()
+}
+
+/// Validates the stored token and updates the config with what Twitch reports about it.
+pub async fn check(config: &mut Config) -> Result<bool> {
+    if !config.is_logged_in() {
+        return Ok(false);
+    }
+    match validate(
+        &http::client(config),
+        &config.auth.base_url(),
+        &config.profile().access_token,
+    )
+    .await?
+    {
+        Some(validation) => {
+            apply_validation(config.profile_mut(), validation);
+            config.save()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Revokes the token with Twitch and removes the credentials from the config.
+pub async fn logout(config: &mut Config) -> Result<()> {
+    if config.is_logged_in() {
+        let res = http::client(config)
+            .post(format!("{}/revoke", config.auth.base_url()))
+            .form(&[
+                ("client_id", config.profile().client_id.as_str()),
+                ("token", config.profile().access_token.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(network)?;
+        // an invalid token is as good as a revoked one
+        if !res.status().is_success() && res.status() != StatusCode::BAD_REQUEST {
+            return Err(error_from(res).await.into());
+        }
+    }
+    config.profile_mut().clear();
+    config.save()
+}
+
+/// Exchanges the stored refresh token for a new access token.
+#[tracing::instrument(name = "refresh", skip_all)]
+pub async fn refresh(config: &mut Config) -> Result<()> {
+    let client = http::client(config);
+    let profile = config.profile();
+    let mut form = vec![
+        ("client_id", profile.client_id.as_str()),
+        ("grant_type", "refresh_token"),
+        ("refresh_token", profile.refresh_token.as_str()),
+    ];
+    // public clients (device flow) refresh without a secret
+    if !config.auth.client_secret.is_empty() {
+        form.push(("client_secret", config.auth.client_secret.as_str()));
+    }
+    let res = client
+        .post(format!("{}/token", config.auth.base_url()))
+        .form(&form)
+        .send()
+        .await
+        .map_err(network)?;
+    debug!(status = res.status().as_u16(), "POST /token");
+    // Twitch answers a refresh token it no longer takes (OAuth's invalid_grant) with a 400,
+    // which `error_from` reports as unauthorized
+    if !res.status().is_success() {
+        return Err(error_from(res).await.into());
+    }
+    let token: Token = json(res).await?;
+    store_token(&client, config, token).await
+}
+
+/// Asks Twitch who a token belongs to, `None` if Twitch no longer accepts it.
+#[tracing::instrument(name = "validate", skip_all)]
+pub async fn validate(
+    client: &Client,
+    auth_url: &str,
+    access_token: &str,
+) -> Result<Option<Validation>> {
+    let res = client
+        .get(format!("{auth_url}/validate"))
+        .header(header::AUTHORIZATION, format!("OAuth {access_token}"))
+        .send()
+        .await
+        .map_err(network)?;
+    debug!(status = res.status().as_u16(), "GET /validate");
+    if res.status() == StatusCode::UNAUTHORIZED {
+        return Ok(None);
+    }
+    if !res.status().is_success() {
+        return Err(error_from(res).await.into());
+    }
+    Ok(Some(json(res).await?))
+}
+
+/// Refreshes the token, and only forgets the credentials when Twitch rejects the refresh token,
+/// so a network error does not log the user out.
+async fn refresh_or_bail(config: &mut Config) -> Result<()> {
+    if !config.profile().refresh_token.is_empty() {
+        match refresh(config).await {
+            Ok(()) => return Ok(()),
+            Err(e) if !matches!(e.downcast_ref(), Some(Error::Unauthorized(_))) => return Err(e),
+            Err(e) => debug!("refresh token rejected: {e}"),
+        }
+    }
+    let profile = config.profile_mut();
+    profile.access_token.clear();
+    profile.refresh_token.clear();
+    profile.expires_at = None;
+    profile.validated_at = None;
+    config.save()?;
+    Err(Error::Unauthorized(String::from(
+        "Your Twitch token has expired and could not be refreshed",
+    ))
+    .into())
+}
+
+async fn store_token(client: &Client, config: &mut Config, token: Token) -> Result<()> {
+    let validation = validate(client, &config.auth.base_url(), &token.access_token)
+        .await?
+        .ok_or_else(|| {
+            Error::Unauthorized(String::from(
+                "Twitch did not accept the token it just issued",
+            ))
+        })?;
+    let profile = config.profile_mut();
+    profile.access_token = token.access_token;
+    if let Some(refresh_token) = token.refresh_token {
+        profile.refresh_token = refresh_token;
+    }
+    apply_validation(profile, validation);
+    if profile.scopes.is_empty() {
+        profile.scopes = token.scope;
+    }
+    config.save()
+}
+
+fn apply_validation(profile: &mut Profile, validation: Validation) {
+    let now = Utc::now();
+    profile.login = validation.login;
+    profile.user_id = validation.user_id;
+    profile.scopes = validation.scopes;
+    profile.expires_at =
+        (validation.expires_in > 0).then(|| now + Duration::seconds(validation.expires_in));
+    profile.validated_at = Some(now);
+}
+
+/// Turns an unsuccessful OAuth response into an error carrying Twitch's message.
+async fn error_from(res: reqwest::Response) -> Error {
+    let status = res.status();
+    let message = res
+        .json::<ErrorResponse>()
+        .await
+        .map(|e| e.message)
+        .unwrap_or_default();
+    rejected(status, &message)
+}
+
+/// The error for an OAuth endpoint answering with `status`: the token or grant was rejected
+/// for a 4xx, Twitch is at fault for anything else.
+fn rejected(status: StatusCode, message: &str) -> Error {
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => Error::RateLimited {
+            service: SERVICE,
+            retry_after: None,
+        },
+        _ if status.is_client_error() && message.is_empty() => {
+            Error::Unauthorized(format!("Twitch authentication failed ({status})"))
+        }
+        _ if status.is_client_error() => Error::Unauthorized(format!(
+            "Twitch authentication failed ({status}): {message}"
+        )),
+        _ => Error::Upstream {
+            service: SERVICE,
+            status,
+        },
+    }
+}
+
+fn network(source: reqwest::Error) -> Error {
+    Error::Network {
+        service: SERVICE,
+        source,
+    }
+}
+
+async fn json<T: serde::de::DeserializeOwned>(res: reqwest::Response) -> Result<T, Error> {
+    let body = res.text().await.map_err(network)?;
+    serde_json::from_str(&body).map_err(|source| Error::Schema {
+        service: SERVICE,
+        source,
+    })
+}
@@ -0,0 +1,152 @@
+use super::{Notice, Token};
+use crate::config::AuthConfig;
+use crate::error::Error;
+use anyhow::{bail, Result};
+use reqwest::{Client, Url};
+use std::future;
+use std::io;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{self, TcpListener, TcpStream};
+
+const DONE_PAGE: &str = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n\
+    <html><body>You are logged in to twitch-lookup, this tab can be closed now.</body></html>";
+const FAILED_PAGE: &str =
+    "HTTP/1.1 400 Bad Request\r\nContent-Type: text/html\r\nConnection: close\r\n\r\n";
+const NOT_FOUND: &str = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// Runs the OAuth authorization code grant: the browser is sent to Twitch, which redirects
+/// back to a one-shot HTTP listener on localhost with the code we exchange for a token.
+pub async fn login(
+    client: &Client,
+    client_id: &str,
+    auth: &AuthConfig,
+    notify: &dyn Fn(Notice),
+) -> Result<Token> {
+    if auth.client_secret.is_empty() {
+        bail!("The redirect login flow needs `client_secret` in the [auth] config section");
+    }
+    let redirect_uri = format!("http://localhost:{}", auth.redirect_port);
+    let state: String = crate::secrets::rand_bytes::<16>()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let scopes = auth.scopes.join(" ");
+    let authorize = Url::parse_with_params(
+        &format!("{}/authorize", auth.base_url()),
+        &[
+            ("response_type", "code"),
+            ("client_id", client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", &scopes),
+            ("state", &state),
+            // lets the user pick another account than the one logged in on twitch.tv
+            ("force_verify", "true"),
+        ],
+    )?;
+
+    let listeners = bind_localhost(auth.redirect_port).await?;
+    notify(Notice::OpenUrl {
+        url: authorize.as_str(),
+    });
+
+    let code = loop {
+        let mut stream = tokio::select! {
+            stream = accept(&listeners[0]) => stream?,
+            stream = accept(&listeners[1]) => stream?,
+        };
+        let mut request_line = String::new();
+        BufReader::new(&mut stream)
+            .read_line(&mut request_line)
+            .await?;
+        // favicon requests and the like can arrive before the redirect itself
+        let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+        let url = Url::parse(&format!("http://localhost{path}"))?;
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.into_owned())
+        };
+        if param("state").is_none() {
+            stream.write_all(NOT_FOUND.as_bytes()).await?;
+            continue;
+        }
+        // the browser only hears of success once the redirect checked out
+        let outcome = if param("state").as_deref() != Some(state.as_str()) {
+            Err(String::from(
+                "The login redirect carried an unexpected state, please try again",
+            ))
+        } else if let Some(error) = param("error_description").or_else(|| param("error")) {
+            Err(format!("The authorization request was denied: {error}"))
+        } else {
+            param("code").ok_or_else(|| String::from("The login redirect carried no code"))
+        };
+        match outcome {
+            Ok(code) => {
+                stream.write_all(DONE_PAGE.as_bytes()).await?;
+                break code;
+            }
+            Err(reason) => {
+                let page = format!(
+                    "{FAILED_PAGE}<html><body>Logging in to twitch-lookup failed: {}</body></html>",
+                    escape_html(&reason)
+                );
+                stream.write_all(page.as_bytes()).await?;
+                return Err(Error::Unauthorized(reason).into());
+            }
+        }
+    };
+
+    let res = client
+        .post(format!("{}/token", auth.base_url()))
+        .form(&[
+            ("client_id", client_id),
+            ("client_secret", &auth.client_secret),
+            ("code", &code),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", &redirect_uri),
+        ])
+        .send()
+        .await
+        .map_err(super::network)?;
+    if !res.status().is_success() {
+        return Err(super::error_from(res).await.into());
+    }
+    Ok(super::json(res).await?)
+}
+
+/// Makes text from the redirect (Twitch's error description) safe to put in the page.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Listens on `localhost:<port>` like the redirect URL says, on both the IPv4 and the IPv6
+/// loopback address when it resolves to both, since the browser may pick either.
+async fn bind_localhost(port: u16) -> Result<[Option<TcpListener>; 2]> {
+    let mut listeners = [None, None];
+    let mut error = None;
+    for addr in net::lookup_host(("localhost", port)).await? {
+        let slot = &mut listeners[usize::from(addr.is_ipv6())];
+        if slot.is_some() {
+            continue;
+        }
+        match TcpListener::bind(addr).await {
+            Ok(listener) => *slot = Some(listener),
+            Err(e) => error = Some(e),
+        }
+    }
+    if listeners.iter().all(Option::is_none) {
+        let error = error.unwrap_or_else(|| io::ErrorKind::AddrNotAvailable.into());
+        bail!("Could not listen on localhost:{port}: {error}");
+    }
+    Ok(listeners)
+}
+
+async fn accept(listener: &Option<TcpListener>) -> io::Result<TcpStream> {
+    match listener {
+        Some(listener) => listener.accept().await.map(|(stream, _)| stream),
+        None => future::pending().await,
+    }
+}
@@ -0,0 +1,11 @@
+/// Opens `url` in the default browser, returning whether that worked.
+#[cfg(feature = "browser")]
+pub fn open(url: &str) -> bool {
+    webbrowser::open(url).is_ok()
+}
+
+/// Builds without the `browser` feature never open anything, callers print the link instead.
+#[cfg(not(feature = "browser"))]
+pub fn open(_url: &str) -> bool {
+    false
+}
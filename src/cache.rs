@@ -0,0 +1,218 @@
+use crate::config::{CacheMode, Config};
+use crate::secrets;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// When the oldest response answered from the cache in this run was stored.
+static OLDEST_SERVED: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// How long a kind of response stays fresh, in seconds taken from the `[cache]` config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ttl {
+    /// Users and their IDs, which rarely change.
+    User,
+    /// Live status, viewer counts and chatters.
+    Live,
+    /// Everything in between: titles, VODs, follow ages, logs.
+    Content,
+}
+
+/// A cached response body.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    service: String,
+    url: String,
+    #[serde(default)]
+    account: Option<String>,
+    stored_at: u64,
+    expires_at: u64,
+    body: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Stats {
+    pub entries: usize,
+    pub expired: usize,
+    pub bytes: u64,
+    pub by_service: BTreeMap<String, usize>,
+    pub path: String,
+}
+
+/// Response bodies on disk in the user's cache directory, one file per service and URL.
+#[derive(Clone)]
+pub struct Cache {
+    dir: PathBuf,
+    mode: CacheMode,
+    ttls: [u64; 3],
+    /// The account answers are cached for, for services whose answers depend on the token.
+    account: Option<String>,
+}
+
+impl Cache {
+    pub fn new(config: &Config) -> Result<Self> {
+        let dir = dirs::cache_dir()
+            .ok_or_else(|| anyhow!("Could not find the cache directory"))?
+            .join("twitch-lookup");
+        let mode = if config.cache.enabled {
+            config.cache.mode
+        } else {
+            CacheMode::Off
+        };
+        Ok(Self {
+            dir,
+            mode,
+            ttls: [config.cache.users, config.cache.live, config.cache.content],
+            account: None,
+        })
+    }
+
+    /// Keeps the answers apart from those cached for other accounts, e.g. `default:123` for
+    /// the profile `default` logged in as user 123.
+    pub fn for_account(mut self, account: String) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    fn ttl(&self, ttl: Ttl) -> u64 {
+        match ttl {
+            Ttl::User => self.ttls[0],
+            Ttl::Live => self.ttls[1],
+            Ttl::Content => self.ttls[2],
+        }
+    }
+
+    /// A fresh cached body for `url`, unless caching is off or `--refresh` was given.
+    /// Offline, any cached body is fresh enough.
+    pub fn get(&self, service: &str, url: &str, ttl: Ttl) -> Option<String> {
+        let offline = self.mode == CacheMode::Offline;
+        if !offline && (self.mode != CacheMode::On || self.ttl(ttl) == 0) {
+            return None;
+        }
+        let entry = read_entry(&self.path(service, url))?;
+        // the file name is a hash, make sure it is really the same request
+        let fresh = offline || entry.expires_at > now();
+        if entry.service != service || entry.url != url || entry.account != self.account || !fresh {
+            return None;
+        }
+        OLDEST_SERVED.fetch_min(entry.stored_at, Ordering::Relaxed);
+        Some(entry.body)
+    }
+
+    /// Stores a body, failures only cost the next lookup a request so they are ignored.
+    pub fn put(&self, service: &str, url: &str, ttl: Ttl, body: &str) {
+        let ttl = self.ttl(ttl);
+        if matches!(self.mode, CacheMode::Off | CacheMode::Offline) || ttl == 0 {
+            return;
+        }
+        let entry = Entry {
+            service: service.to_string(),
+            url: url.to_string(),
+            account: self.account.clone(),
+            stored_at: now(),
+            expires_at: now() + ttl,
+            body: body.to_string(),
+        };
+        if let Ok(json) = serde_json::to_vec(&entry) {
+            let _ = fs::create_dir_all(&self.dir);
+            // answers made with a token are the account's own, and a reader should never see
+            // half an entry
+            let _ = secrets::write_private(&self.path(service, url), &json);
+        }
+    }
+
+    pub fn stats(&self) -> Result<Stats> {
+        let mut stats = Stats {
+            path: self.dir.display().to_string(),
+            ..Default::default()
+        };
+        for (path, entry) in self.entries()? {
+            stats.entries += 1;
+            stats.bytes += fs::metadata(&path).map_or(0, |m| m.len());
+            match entry {
+                Some(entry) => {
+                    if entry.expires_at <= now() {
+                        stats.expired += 1;
+                    }
+                    *stats.by_service.entry(entry.service).or_default() += 1;
+                }
+                None => stats.expired += 1,
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Removes every entry, returning how many there were.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = self.entries()?;
+        for (path, _) in &entries {
+            fs::remove_file(path)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Removes expired and unreadable entries, returning how many were removed.
+    pub fn prune(&self) -> Result<usize> {
+        let mut removed = 0;
+        for (path, entry) in self.entries()? {
+            if entry.is_none_or(|e| e.expires_at <= now()) {
+                fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn entries(&self) -> Result<Vec<(PathBuf, Option<Entry>)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                let entry = read_entry(&path);
+                entries.push((path, entry));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn path(&self, service: &str, url: &str) -> PathBuf {
+        let key = match &self.account {
+            Some(account) => format!("{service} {url} as {account}"),
+            None => format!("{service} {url}"),
+        };
+        self.dir.join(format!("{:016x}.json", fnv1a(&key)))
+    }
+}
+
+/// When the oldest response served from the cache in this run was fetched.
+pub fn oldest_served() -> Option<DateTime<Utc>> {
+    match OLDEST_SERVED.load(Ordering::Relaxed) {
+        u64::MAX => None,
+        stored_at => Utc.timestamp_opt(stored_at as i64, 0).single(),
+    }
+}
+
+fn read_entry(path: &PathBuf) -> Option<Entry> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// FNV-1a, a hash that stays the same across builds so file names survive upgrades.
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,8 +1,93 @@
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use twitch_lookup::color::ColorChoice;
+use twitch_lookup::output::OutputFormat;
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about = "A simple twitch account lookup tool", long_about = None)]
 pub struct Args {
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        default_value = "text",
+        help = "Output format"
+    )]
+    pub output: OutputFormat,
+    #[clap(
+        long,
+        global = true,
+        arg_enum,
+        default_value = "auto",
+        help = "When to use colors (NO_COLOR and CLICOLOR_FORCE are honored for auto)"
+    )]
+    pub color: ColorChoice,
+    #[clap(
+        long,
+        global = true,
+        help = "Render users, chats, VODs and streams with a template or a named template from the config"
+    )]
+    pub format: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        env = "TL_PROFILE",
+        help = "Account profile to run as instead of the default one"
+    )]
+    pub profile: Option<String>,
+    #[clap(
+        long,
+        global = true,
+        help = "Neither read nor write the response cache"
+    )]
+    pub no_cache: bool,
+    #[clap(
+        long,
+        global = true,
+        conflicts_with = "no-cache",
+        help = "Ignore cached responses but cache the new ones"
+    )]
+    pub refresh: bool,
+    #[clap(
+        long,
+        global = true,
+        conflicts_with_all = &["no-cache", "refresh"],
+        help = "Answer user, title, ll and vods from the cache without going online"
+    )]
+    pub offline: bool,
+    #[clap(
+        long,
+        global = true,
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with_all = &["offline", "replay"],
+        help = "Save every response from the services to DIR, with tokens redacted, to attach to a bug report"
+    )]
+    pub record: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with = "offline",
+        help = "Answer from the responses saved to DIR with --record instead of going online"
+    )]
+    pub replay: Option<PathBuf>,
+    #[clap(
+        long,
+        global = true,
+        conflicts_with_all = &["record", "replay"],
+        help = "Print the request the command would send instead of sending it"
+    )]
+    pub dry_run: bool,
+    #[clap(
+        short,
+        long,
+        global = true,
+        parse(from_occurrences),
+        help = "Trace the requests to stderr, -vv also traces the responses (TL_LOG takes a filter instead)"
+    )]
+    pub verbose: u8,
     #[clap(subcommand)]
     pub action: Action,
 }
@@ -10,34 +95,47 @@ pub struct Args {
 #[non_exhaustive]
 #[derive(Subcommand, Debug)]
 pub enum Action {
+    #[cfg(feature = "ivr")]
     #[clap(subcommand, about = "Get general user account information")]
     User(UserAction),
+    #[cfg(feature = "chat")]
     #[clap(subcommand, about = "Get chat information for a given account")]
     Chat(ChatAction),
+    #[cfg(feature = "decapi")]
     #[clap(about = "Check if channel is live and how many viewers they have at the moment")]
     Live { channel: String },
     #[clap(about = "Open up a given bot command in your default browser")]
     Command { bot: String, cmd_name: String },
+    #[cfg(feature = "ivr")]
     #[clap(about = "Open up a given users logs in a given channel in your default browser")]
     Logs {
         user: String,
         channel: Option<String>,
     },
+    #[cfg(feature = "decapi")]
     #[clap(about = "Check how long a user has followed a given channel")]
     Fa {
         user: String,
         channel: Option<String>,
     },
+    #[cfg(feature = "decapi")]
     #[clap(about = "Get the stream title from a given channel")]
     Title { channel: String },
+    #[cfg(feature = "helix")]
     #[clap(about = "Get VOD(s) from a given channel")]
     Vods { channel: String, amount: Option<u8> },
+    #[cfg(feature = "helix")]
     #[clap(about = "Get streams from your follow directory")]
     Ll,
     #[clap(about = "emotes.raccatta.cc emote lookup for a user")]
     Emotes { user: String },
+    #[clap(subcommand, about = "Manage the Twitch account tl is logged in with")]
+    Auth(AuthAction),
+    #[clap(subcommand, about = "Inspect and clean up the response cache")]
+    Cache(CacheAction),
 }
 
+#[cfg(feature = "ivr")]
 #[derive(Subcommand, Debug)]
 pub enum UserAction {
     #[clap(about = "Compact list of user information")]
@@ -78,12 +176,249 @@ pub enum UserAction {
     Link { user: String },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum AuthAction {
+    #[clap(about = "Log in to Twitch")]
+    Login {
+        #[clap(
+            long,
+            help = "Use an existing access token instead of logging in interactively (for CI)"
+        )]
+        token: Option<String>,
+    },
+    #[clap(about = "Show the logged in account, its scopes and when the token expires")]
+    Status,
+    #[clap(about = "Revoke the token and remove it from the config")]
+    Logout,
+    #[clap(about = "List the profiles that are logged in, * marks the default")]
+    Profiles,
+    #[clap(
+        about = "Make a profile the default (logging in if needed), or log in again with another account"
+    )]
+    Switch { profile: Option<String> },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    #[clap(about = "Show how many responses are cached and how much space they take")]
+    Stats,
+    #[clap(about = "Remove every cached response")]
+    Clear,
+    #[clap(about = "Remove expired responses")]
+    Prune,
+}
+
+#[cfg(feature = "chat")]
 #[derive(Subcommand, Debug)]
 pub enum ChatAction {
-    Streamer { channel: String },
-    Mods { channel: String },
-    Vips { channel: String },
-    Normals { channel: String },
-    Count { channel: String },
-    Present { user: String, channel: String },
+    Streamer {
+        channel: String,
+    },
+    Mods {
+        channel: String,
+    },
+    Vips {
+        channel: String,
+    },
+    Normals {
+        channel: String,
+    },
+    Count {
+        channel: String,
+    },
+    Present {
+        user: String,
+        channel: String,
+    },
+    #[clap(about = "Follow a channel's chat live")]
+    Tail {
+        channel: String,
+        #[clap(short = 'n', long, help = "Stop after this many messages")]
+        count: Option<usize>,
+        #[clap(flatten)]
+        filters: TailFilters,
+    },
+    #[clap(about = "Write the chat of channels to daily log files until stopped")]
+    Record {
+        #[clap(required = true)]
+        channels: Vec<String>,
+        #[clap(
+            long,
+            value_name = "DIR",
+            parse(from_os_str),
+            help = "Write the logs here instead of the [chat] record_dir"
+        )]
+        dir: Option<PathBuf>,
+    },
+    #[clap(about = "Send a message to a channel's chat")]
+    Say {
+        channel: String,
+        #[clap(required = true)]
+        message: Vec<String>,
+    },
+    #[clap(about = "Reply to a message in a channel's chat")]
+    Reply {
+        channel: String,
+        #[clap(help = "The ID of the message to reply to")]
+        message_id: String,
+        #[clap(required = true)]
+        message: Vec<String>,
+    },
+}
+
+/// Which messages `chat tail` shows and highlights, on top of the `[chat]` config section.
+#[cfg(feature = "chat")]
+#[derive(clap::Args, Debug)]
+pub struct TailFilters {
+    #[clap(
+        long = "user",
+        value_name = "LOGIN",
+        help = "Only show messages from this user (repeatable)"
+    )]
+    pub users: Vec<String>,
+    #[clap(
+        long,
+        value_name = "REGEX",
+        help = "Only show messages matching a regex"
+    )]
+    pub grep: Option<String>,
+    #[clap(long, help = "Hide messages from the bots listed in the config")]
+    pub exclude_bots: bool,
+    #[clap(long, help = "Only show messages from the broadcaster and moderators")]
+    pub mods_only: bool,
+    #[clap(long, help = "Only show messages mentioning your account")]
+    pub mentions: bool,
+    #[clap(
+        long = "highlight",
+        value_name = "WORD",
+        help = "Highlight messages containing a word (repeatable)"
+    )]
+    pub highlights: Vec<String>,
+    #[clap(long, help = "Ring the terminal bell for highlighted messages")]
+    pub bell: bool,
+}
+
+impl Action {
+    /// Whether the action needs the logged in account, either for a Helix token or as the
+    /// default user.
+    pub fn needs_login(&self) -> bool {
+        match self {
+            #[cfg(feature = "helix")]
+            Action::Vods { .. } | Action::Ll => true,
+            // chat is read anonymously unless the token may read it
+            #[cfg(feature = "chat")]
+            Action::Chat(chat_action) => !chat_action.is_live(),
+            #[cfg(feature = "ivr")]
+            Action::Logs { channel, .. } => channel.is_none(),
+            #[cfg(feature = "decapi")]
+            Action::Fa { channel, .. } => channel.is_none(),
+            _ => false,
+        }
+    }
+
+    /// Whether the action can be answered from the cache alone.
+    pub fn works_offline(&self) -> bool {
+        match self {
+            #[cfg(feature = "ivr")]
+            Action::User(_) => true,
+            #[cfg(feature = "decapi")]
+            Action::Title { .. } => true,
+            #[cfg(feature = "helix")]
+            Action::Ll | Action::Vods { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the action asks the services through the shared client, as opposed to only
+    /// opening links, logging in or managing the cache.
+    pub fn calls_apis(&self) -> bool {
+        // live chat is streamed over its own connection
+        #[cfg(feature = "chat")]
+        if let Action::Chat(chat_action) = self {
+            if chat_action.is_live() {
+                return false;
+            }
+        }
+        !matches!(
+            self,
+            Action::Command { .. } | Action::Emotes { .. } | Action::Auth(_) | Action::Cache(_)
+        )
+    }
+
+    /// Whether the action reads or changes the stored tokens.
+    pub fn needs_secrets(&self) -> bool {
+        self.needs_login() || matches!(self, Action::Auth(_))
+    }
+
+    /// Token scopes the action needs on top of being logged in.
+    pub fn required_scopes(&self) -> Vec<&'static str> {
+        match self {
+            #[cfg(feature = "helix")]
+            Action::Ll => vec!["user:read:follows"],
+            #[cfg(feature = "chat")]
+            Action::Chat(chat_action) => chat_action.required_scopes(),
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl UserAction {
+    pub fn user(&self) -> &str {
+        match self {
+            UserAction::Compact { user }
+            | UserAction::Verbose { user }
+            | UserAction::Id { user }
+            | UserAction::Bc { user }
+            | UserAction::Dn { user }
+            | UserAction::Uf { user }
+            | UserAction::Fu { user }
+            | UserAction::Cv { user }
+            | UserAction::Cc { user }
+            | UserAction::Pfp { user }
+            | UserAction::Bot { user }
+            | UserAction::Cd { user }
+            | UserAction::Ep { user }
+            | UserAction::Roles { user }
+            | UserAction::Badges { user }
+            | UserAction::Cs { user }
+            | UserAction::Dt { user }
+            | UserAction::Link { user } => user,
+        }
+    }
+}
+
+#[cfg(feature = "chat")]
+impl ChatAction {
+    /// Whether the action reads chat over IRC, which works without an account, rather than
+    /// asking Helix who is in it.
+    pub fn is_live(&self) -> bool {
+        matches!(self, ChatAction::Tail { .. } | ChatAction::Record { .. })
+    }
+
+    /// Scopes the action needs. Sending takes either `user:write:chat` or `chat:edit`, which
+    /// is checked when the message is sent.
+    pub fn required_scopes(&self) -> Vec<&'static str> {
+        match self {
+            ChatAction::Say { .. } | ChatAction::Reply { .. } => vec![],
+            _ if self.is_live() => vec![],
+            _ => vec!["moderator:read:chatters"],
+        }
+    }
+
+    /// The channel the action looks at, the first one for `record`.
+    pub fn channel(&self) -> &str {
+        match self {
+            ChatAction::Streamer { channel }
+            | ChatAction::Mods { channel }
+            | ChatAction::Vips { channel }
+            | ChatAction::Normals { channel }
+            | ChatAction::Count { channel }
+            | ChatAction::Present { channel, .. }
+            | ChatAction::Tail { channel, .. }
+            | ChatAction::Say { channel, .. }
+            | ChatAction::Reply { channel, .. } => channel,
+            ChatAction::Record { channels, .. } => &channels[0],
+        }
+    }
 }
@@ -0,0 +1,133 @@
+use clap::ArgEnum;
+use colored::{Color, Colorize};
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+/// How many colors the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+static DEPTH: AtomicU8 = AtomicU8::new(ColorDepth::TrueColor as u8);
+
+/// The 16 basic ANSI colors and their usual xterm RGB values, used for the lowest fallback.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::White, (229, 229, 229)),
+    (Color::BrightBlack, (127, 127, 127)),
+    (Color::BrightRed, (255, 0, 0)),
+    (Color::BrightGreen, (0, 255, 0)),
+    (Color::BrightYellow, (255, 255, 0)),
+    (Color::BrightBlue, (92, 92, 255)),
+    (Color::BrightMagenta, (255, 0, 255)),
+    (Color::BrightCyan, (0, 255, 255)),
+    (Color::BrightWhite, (255, 255, 255)),
+];
+
+/// Decides whether output is colored and at which depth, then applies it globally.
+///
+/// `--color always|never` wins, otherwise `NO_COLOR` disables and `CLICOLOR_FORCE` enables
+/// colors, falling back to whether stdout is a terminal.
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => auto_enabled(),
+    };
+    ENABLED.store(enabled, Ordering::Relaxed);
+    DEPTH.store(detect_depth() as u8, Ordering::Relaxed);
+    colored::control::set_override(enabled);
+}
+
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn depth() -> ColorDepth {
+    match DEPTH.load(Ordering::Relaxed) {
+        0 => ColorDepth::Ansi16,
+        1 => ColorDepth::Ansi256,
+        _ => ColorDepth::TrueColor,
+    }
+}
+
+/// Colors `text` with an RGB color, degrading to the closest color the terminal supports.
+pub fn rgb(text: &str, (r, g, b): (u8, u8, u8)) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    match depth() {
+        ColorDepth::TrueColor => text.truecolor(r, g, b).to_string(),
+        ColorDepth::Ansi256 => format!("\x1b[38;5;{}m{text}\x1b[0m", to_ansi256(r, g, b)),
+        ColorDepth::Ansi16 => text.color(to_ansi16(r, g, b)).to_string(),
+    }
+}
+
+fn auto_enabled() -> bool {
+    if env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    if env::var("CLICOLOR_FORCE").is_ok_and(|v| !v.is_empty() && v != "0") {
+        return true;
+    }
+    io::stdout().is_terminal() && env::var("TERM").map_or(true, |t| t != "dumb")
+}
+
+fn detect_depth() -> ColorDepth {
+    if let Ok(colorterm) = env::var("COLORTERM") {
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorDepth::TrueColor;
+        }
+    }
+    match env::var("TERM") {
+        Ok(term) if term.contains("256color") => ColorDepth::Ansi256,
+        Ok(term) if term.contains("truecolor") || term.contains("direct") => ColorDepth::TrueColor,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+fn to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    if r == g && g == b {
+        // grayscale ramp (232-255) gives a finer resolution than the color cube for grays
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r as u16 - 8) * 24 / 241) as u8,
+        };
+    }
+    let level = |c: u8| ((c as u16 * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+fn to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    let distance = |(cr, cg, cb): (u8, u8, u8)| {
+        let dr = r as i32 - cr as i32;
+        let dg = g as i32 - cg as i32;
+        let db = b as i32 - cb as i32;
+        dr * dr + dg * dg + db * db
+    };
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance(*rgb))
+        .map(|(color, _)| *color)
+        .unwrap_or(Color::White)
+}
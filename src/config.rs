@@ -1,48 +1,470 @@
-use anyhow::Result;
+use crate::recording::Recording;
+use crate::secrets::{self, SecretStoreKind};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+#[cfg(feature = "interactive-config")]
 use dialoguer::{theme::ColorfulTheme, Input};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tracing::info;
 
-#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct Config {
+    /// Profile used when neither `--profile` nor `TL_PROFILE` is given.
+    #[serde(default = "default_profile")]
+    pub default_profile: String,
+    /// Client ID new profiles log in with.
+    #[serde(default)]
+    pub client_id: String,
+    /// Where tokens and the client secret are stored.
+    #[serde(default)]
+    pub secret_store: SecretStoreKind,
+    /// Named output templates usable with `--format <name>`.
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub endpoints: Endpoints,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub chat: ChatConfig,
+    /// Accounts by profile name.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// The profile commands run as.
+    #[serde(skip)]
+    pub active_profile: String,
+    /// Whether the secrets were loaded from the secret store.
+    #[serde(skip)]
+    unlocked: bool,
+    // Single-account configs from before profiles existed, moved into the default profile on read.
+    #[serde(default, skip_serializing)]
+    login: String,
+    #[serde(default, skip_serializing)]
+    user_id: String,
+    #[serde(default, skip_serializing)]
+    access_token: String,
+}
+
+/// A Twitch account and its token.
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
+#[serde(default)]
+pub struct Profile {
     pub login: String,
     pub user_id: String,
     pub client_id: String,
+    #[serde(skip_serializing)]
     pub access_token: String,
+    #[serde(skip_serializing)]
+    pub refresh_token: String,
+    /// Scopes granted to the access token.
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub validated_at: Option<DateTime<Utc>>,
+}
+
+/// Settings for obtaining a token from Twitch.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Base URL of the Twitch OAuth2 endpoints (`/device`, `/token`, `/authorize`, `/validate`).
+    pub url: String,
+    pub flow: AuthFlow,
+    pub scopes: Vec<String>,
+    /// Only needed for the `redirect` flow.
+    #[serde(skip_serializing)]
+    pub client_secret: String,
+    /// Local port the `redirect` flow listens on, `http://localhost:<port>` has to be a
+    /// registered redirect URL of the application.
+    pub redirect_port: u16,
+}
+
+/// Base URLs of the services `tl` talks to, for pointing it at mirrors, a proxy or a mock server.
+/// Each one can also be overridden by an environment variable, which wins over the config file.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct Endpoints {
+    /// Twitch Helix API, `TL_HELIX_URL`.
+    pub helix: String,
+    /// ivr.fi user API, `TL_IVR_URL`.
+    pub ivr: String,
+    /// justlog instance for `tl logs`, `TL_LOGS_URL`.
+    pub logs: String,
+    /// DecAPI, `TL_DECAPI_URL`.
+    pub decapi: String,
+    /// Twitch chat over IRC on a WebSocket, `TL_IRC_URL`.
+    pub irc: String,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Self {
+            helix: String::from("https://api.twitch.tv/helix"),
+            ivr: String::from("https://api.ivr.fi/v2"),
+            logs: String::from("https://logs.ivr.fi"),
+            decapi: String::from("https://decapi.me"),
+            irc: String::from("wss://irc-ws.chat.twitch.tv:443"),
+        }
+    }
+}
+
+impl Endpoints {
+    pub fn helix(&self) -> String {
+        endpoint("TL_HELIX_URL", &self.helix)
+    }
+
+    pub fn ivr(&self) -> String {
+        endpoint("TL_IVR_URL", &self.ivr)
+    }
+
+    pub fn logs(&self) -> String {
+        endpoint("TL_LOGS_URL", &self.logs)
+    }
+
+    pub fn decapi(&self) -> String {
+        endpoint("TL_DECAPI_URL", &self.decapi)
+    }
+
+    pub fn irc(&self) -> String {
+        endpoint("TL_IRC_URL", &self.irc)
+    }
+}
+
+/// How requests to the services are sent.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Seconds before a request is given up on.
+    pub timeout: u64,
+    /// How often a failed GET is retried.
+    pub retries: u32,
+    /// Set by `--record` and `--replay` for a single run.
+    #[serde(skip)]
+    pub recording: Recording,
+    /// Set by `--dry-run`, requests are noted with [`crate::http::would_send`] instead of being
+    /// sent and answered with made-up data.
+    #[serde(skip)]
+    pub dry_run: bool,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            timeout: 10,
+            retries: 3,
+            recording: Recording::default(),
+            dry_run: false,
+        }
+    }
+}
+
+/// How long responses are reused from the on-disk cache.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    /// Seconds user lookups stay cached.
+    pub users: u64,
+    /// Seconds live status, viewer counts and chatters stay cached.
+    pub live: u64,
+    /// Seconds titles, VODs, follow ages and logs stay cached.
+    pub content: u64,
+    /// Set by `--refresh`, `--no-cache` and `--offline` for a single run.
+    #[serde(skip)]
+    pub mode: CacheMode,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum CacheMode {
+    /// Answer from the cache when possible.
+    #[default]
+    On,
+    /// Always ask the services, but keep caching the answers.
+    Refresh,
+    /// Neither read nor write the cache.
+    Off,
+    /// Only answer from the cache, however old the answer is.
+    Offline,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            users: 24 * 60 * 60,
+            live: 60,
+            content: 10 * 60,
+            mode: CacheMode::default(),
+        }
+    }
+}
+
+/// Rules `tl chat tail` applies to every message, on top of the ones passed as flags.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default)]
+pub struct ChatConfig {
+    /// Words highlighting a message, matched as whole words regardless of case.
+    pub highlights: Vec<String>,
+    /// Logins whose messages are never shown.
+    pub ignore_users: Vec<String>,
+    /// Regexes, messages with matching text are never shown.
+    pub ignore: Vec<String>,
+    /// Logins `--exclude-bots` hides.
+    pub bots: Vec<String>,
+    /// Ring the terminal bell for highlighted messages.
+    pub bell: bool,
+    /// Shell command run for each highlighted message, which it finds in the `TL_CHAT_*`
+    /// environment variables.
+    pub hook: String,
+    /// Where `tl chat record` writes its logs, `twitch-lookup/chat` in the data directory
+    /// when empty.
+    pub record_dir: String,
+    /// Days `tl chat record` keeps logs for, 0 keeps them forever.
+    pub keep_days: u32,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            highlights: Vec::new(),
+            ignore_users: Vec::new(),
+            ignore: Vec::new(),
+            bots: [
+                "fossabot",
+                "moobot",
+                "nightbot",
+                "pajbot",
+                "streamelements",
+                "streamlabs",
+                "supibot",
+                "wizebot",
+            ]
+            .map(String::from)
+            .to_vec(),
+            bell: false,
+            hook: String::new(),
+            record_dir: String::new(),
+            keep_days: 0,
+        }
+    }
+}
+
+impl ChatConfig {
+    /// The directory `tl chat record` writes to.
+    pub fn record_dir(&self) -> Result<PathBuf> {
+        if !self.record_dir.is_empty() {
+            return Ok(PathBuf::from(&self.record_dir));
+        }
+        dirs::data_dir()
+            .map(|dir| dir.join("twitch-lookup/chat"))
+            .ok_or_else(|| anyhow!("Could not find the data directory, set record_dir in [chat]"))
+    }
+}
+
+impl AuthConfig {
+    /// The OAuth2 base URL, `TL_AUTH_URL` overrides `url`.
+    pub fn base_url(&self) -> String {
+        endpoint("TL_AUTH_URL", &self.url)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthFlow {
+    Device,
+    Redirect,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            url: String::from("https://id.twitch.tv/oauth2"),
+            flow: AuthFlow::Device,
+            scopes: vec![
+                String::from("user:read:follows"),
+                String::from("moderator:read:chatters"),
+            ],
+            client_secret: String::new(),
+            redirect_port: 3000,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            default_profile: default_profile(),
+            client_id: String::new(),
+            secret_store: SecretStoreKind::default(),
+            templates: BTreeMap::new(),
+            auth: AuthConfig::default(),
+            endpoints: Endpoints::default(),
+            http: HttpConfig::default(),
+            cache: CacheConfig::default(),
+            chat: ChatConfig::default(),
+            profiles: BTreeMap::new(),
+            active_profile: default_profile(),
+            unlocked: false,
+            login: String::new(),
+            user_id: String::new(),
+            access_token: String::new(),
+        }
+    }
 }
 
 impl Config {
-    pub fn read() -> Result<Self> {
-        let cfg = confy::load::<Config>("twitch-lookup")?;
-        if cfg == Config::default() {
-            webbrowser::open("https://rusterino.waalrus.xyz/login").unwrap();
-            let response = prompt("Paste your credentials here");
-            let mut split = response.split(';');
-            let login = split.next().unwrap().to_owned();
-            let user_id = split.next().unwrap().to_owned();
-            let client_id = split.next().unwrap().to_owned();
-            let access_token = split.next().unwrap().to_owned();
-            let cfg = Self {
-                login,
-                user_id,
-                client_id,
-                access_token,
+    /// Reads the config with `profile` (or the default profile) as the active one.
+    pub fn read(profile: Option<&str>) -> Result<Self> {
+        let mut cfg = confy::load::<Config>("twitch-lookup")?;
+        if cfg.profiles.is_empty() && !cfg.access_token.is_empty() {
+            let legacy = Profile {
+                login: std::mem::take(&mut cfg.login),
+                user_id: std::mem::take(&mut cfg.user_id),
+                client_id: cfg.client_id.clone(),
+                access_token: std::mem::take(&mut cfg.access_token),
+                ..Default::default()
             };
-            confy::store("twitch-lookup", &cfg).unwrap();
-            Ok(cfg)
-        } else {
-            Ok(cfg)
+            cfg.profiles.insert(cfg.default_profile.clone(), legacy);
+        }
+        cfg.active_profile = profile.unwrap_or(&cfg.default_profile).to_string();
+        cfg.profiles.entry(cfg.active_profile.clone()).or_default();
+        // secrets are never written to the config file, any found there predate the secret store
+        let plaintext = !cfg.auth.client_secret.is_empty()
+            || cfg
+                .profiles
+                .values()
+                .any(|p| !p.access_token.is_empty() || !p.refresh_token.is_empty());
+        if plaintext {
+            cfg.unlock()?;
+            cfg.save()?;
+            info!(
+                "Moved the credentials from the config file into {}",
+                secrets::store(cfg.secret_store)?.name()
+            );
         }
+        Ok(cfg)
+    }
+
+    /// Loads tokens and the client secret from the secret store, which may ask for a passphrase.
+    pub fn unlock(&mut self) -> Result<()> {
+        if self.unlocked {
+            return Ok(());
+        }
+        let store = secrets::store(self.secret_store)?;
+        for (name, profile) in self.profiles.iter_mut() {
+            if profile.access_token.is_empty() {
+                profile.access_token = store
+                    .get(&secret_key(name, "access_token"))?
+                    .unwrap_or_default();
+            }
+            if profile.refresh_token.is_empty() {
+                profile.refresh_token = store
+                    .get(&secret_key(name, "refresh_token"))?
+                    .unwrap_or_default();
+            }
+        }
+        if self.auth.client_secret.is_empty() {
+            self.auth.client_secret = store.get("client_secret")?.unwrap_or_default();
+        }
+        self.unlocked = true;
+        Ok(())
     }
 
-    pub fn _save(&self) -> Result<()> {
-        confy::store("twitch-lookup", self).unwrap();
+    pub fn save(&self) -> Result<()> {
+        let mut cfg = self.clone();
+        // profiles that were only looked at are not worth persisting
+        cfg.profiles
+            .retain(|name, p| name == &cfg.default_profile || *p != Profile::default());
+        if cfg.unlocked {
+            let store = secrets::store(cfg.secret_store)?;
+            for (name, profile) in cfg.profiles.iter() {
+                put_secret(
+                    store,
+                    &secret_key(name, "access_token"),
+                    &profile.access_token,
+                )?;
+                put_secret(
+                    store,
+                    &secret_key(name, "refresh_token"),
+                    &profile.refresh_token,
+                )?;
+            }
+            put_secret(store, "client_secret", &cfg.auth.client_secret)?;
+        }
+        confy::store("twitch-lookup", &cfg)?;
         Ok(())
     }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profiles[&self.active_profile]
+    }
+
+    pub fn profile_mut(&mut self) -> &mut Profile {
+        self.profiles
+            .entry(self.active_profile.clone())
+            .or_default()
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.profile().is_logged_in()
+    }
+}
+
+impl Profile {
+    pub fn is_logged_in(&self) -> bool {
+        !self.access_token.is_empty()
+    }
+
+    /// Forgets the account and its token.
+    pub fn clear(&mut self) {
+        *self = Profile {
+            client_id: std::mem::take(&mut self.client_id),
+            ..Default::default()
+        };
+    }
 }
 
-fn prompt(msg: &str) -> String {
-    Input::with_theme(&ColorfulTheme::default())
+fn secret_key(profile: &str, name: &str) -> String {
+    format!("profiles/{profile}/{name}")
+}
+
+fn put_secret(store: &dyn secrets::SecretStore, key: &str, value: &str) -> Result<()> {
+    if value.is_empty() {
+        store.delete(key)
+    } else {
+        store.set(key, value)
+    }
+}
+
+/// Resolves an endpoint, preferring a non-empty environment variable over the configured URL.
+fn endpoint(var: &str, configured: &str) -> String {
+    let url = std::env::var(var)
+        .ok()
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| configured.to_string());
+    url.trim_end_matches('/').to_string()
+}
+
+fn default_profile() -> String {
+    String::from(DEFAULT_PROFILE)
+}
+
+#[cfg(feature = "interactive-config")]
+pub fn prompt(msg: &str) -> Result<String> {
+    let input = Input::with_theme(&ColorfulTheme::default())
         .with_prompt(msg)
-        .interact()
-        .unwrap()
+        .interact_text()?;
+    Ok(input)
+}
+
+#[cfg(not(feature = "interactive-config"))]
+pub fn prompt(msg: &str) -> Result<String> {
+    anyhow::bail!("This build cannot ask for \"{msg}\", set it in the config file instead")
 }
@@ -1,33 +1,54 @@
-use anyhow::Result;
-use reqwest::get;
+use crate::cache::Ttl;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::http::Http;
 
-pub async fn follow_age(user: &str, channel: &str) -> Result<String> {
-    let followage = get(format!(
-        "https://decapi.me/twitch/followage/{channel}/{user}"
-    ))
-    .await?
-    .text()
-    .await?;
-    Ok(followage)
+/// Client for the DecAPI text endpoints.
+pub struct Api {
+    http: Http,
+    url: String,
 }
 
-pub async fn title(channel: &str) -> Result<String> {
-    let title = get(format!("https://decapi.me/twitch/status/{channel}"))
-        .await?
-        .text()
-        .await?;
-    Ok(title)
-}
+impl Api {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            http: Http::new(config, "DecAPI"),
+            url: config.endpoints.decapi(),
+        }
+    }
 
-pub async fn is_live(channel: &str) -> Option<String> {
-    let response = get(format!("https://decapi.me/twitch/viewercount/{channel}"))
-        .await
-        .unwrap()
-        .text()
+    /// DecAPI answers with a 200 and a plain text message even when something went wrong.
+    async fn get(&self, path: &str, what: &str, ttl: Ttl) -> Result<String> {
+        let url = format!("{}/twitch/{path}", self.url);
+        let text = self.http.get(&url, what, ttl).await?.body;
+        if text.starts_with("User not found") {
+            return Err(Error::NotFound(what.to_string()));
+        }
+        Ok(text)
+    }
+
+    pub async fn follow_age(&self, user: &str, channel: &str) -> Result<String> {
+        self.get(
+            &format!("followage/{channel}/{user}"),
+            &format!("{user} or {channel}"),
+            Ttl::Content,
+        )
         .await
-        .unwrap();
-    if response.contains("offline") {
-        return None;
     }
-    Some(response)
+
+    pub async fn title(&self, channel: &str) -> Result<String> {
+        self.get(&format!("status/{channel}"), channel, Ttl::Content)
+            .await
+    }
+
+    /// The viewer count when `channel` is live, `None` when it is offline.
+    pub async fn is_live(&self, channel: &str) -> Result<Option<String>> {
+        let response = self
+            .get(&format!("viewercount/{channel}"), channel, Ttl::Live)
+            .await?;
+        if response.contains("offline") {
+            return Ok(None);
+        }
+        Ok(Some(response))
+    }
 }
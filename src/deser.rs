@@ -27,6 +27,15 @@ where
     Ok(Duration::minutes(minutes))
 }
 
+/// Reads `null` as the default value of the type.
+pub fn deserialize_null_default<'de, D, T>(data: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(data)?.unwrap_or_default())
+}
+
 pub fn deserialize_date_time<'de, D, S>(data: D) -> Result<S, D::Error>
 where
     D: Deserializer<'de>,
@@ -37,6 +46,7 @@ where
     S::from_str(&s).map_err(de::Error::custom)
 }
 
+#[cfg(feature = "helix")]
 pub fn deserialize_stream_status<'de, D>(data: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
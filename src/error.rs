@@ -0,0 +1,100 @@
+use reqwest::StatusCode;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What can go wrong talking to the APIs `tl` is built on.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("{0} could not be found")]
+    NotFound(String),
+    #[error("{0} is banned")]
+    Banned(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{service} is rate limiting requests")]
+    RateLimited {
+        service: &'static str,
+        retry_after: Option<u64>,
+    },
+    #[error("Could not reach {service}")]
+    Network {
+        service: &'static str,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[cfg(feature = "chat")]
+    #[error("Could not reach {service}")]
+    WebSocket {
+        service: &'static str,
+        #[source]
+        source: Box<tokio_tungstenite::tungstenite::Error>,
+    },
+    #[error("{service} failed with {status}")]
+    Upstream {
+        service: &'static str,
+        status: StatusCode,
+    },
+    /// A chat message Twitch would not or did not deliver, with the reason.
+    #[error("{0}")]
+    Dropped(String),
+    #[error("{0} is not cached")]
+    NotCached(String),
+    #[error("{0} was not recorded")]
+    NotRecorded(String),
+    #[error("{service} sent a response tl does not understand")]
+    Schema {
+        service: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl Error {
+    /// The process exit code `tl` uses for the error, 1 is left for errors outside the API layer.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Invalid(_) => 2,
+            Error::NotFound(_) => 3,
+            Error::Banned(_) => 4,
+            Error::Unauthorized(_) | Error::Forbidden(_) | Error::Dropped(_) => 5,
+            Error::RateLimited { .. } => 6,
+            Error::Network { .. } => 7,
+            #[cfg(feature = "chat")]
+            Error::WebSocket { .. } => 7,
+            Error::Upstream { .. } | Error::Schema { .. } => 8,
+            Error::NotCached(_) | Error::NotRecorded(_) => 9,
+        }
+    }
+
+    /// What the user can do about the error, if anything.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Error::Unauthorized(_) => Some(String::from("Run `tl auth login` to log in again.")),
+            Error::RateLimited {
+                retry_after: Some(secs),
+                ..
+            } => Some(format!("Try again in {secs}s.")),
+            Error::RateLimited { .. } => Some(String::from("Try again in a moment.")),
+            Error::NotCached(_) => Some(String::from("Run the command without --offline to fetch it.")),
+            Error::NotRecorded(_) => Some(String::from(
+                "Replay the command the recording was made with, or record it again with --record.",
+            )),
+            #[cfg(feature = "chat")]
+            Error::WebSocket { .. } => Some(String::from(
+                "Check your connection and the [endpoints] section of the config.",
+            )),
+            Error::Network { .. } => Some(String::from(
+                "Check your connection and the [endpoints] section of the config.",
+            )),
+            Error::Upstream { .. } => Some(String::from("The service may be down, try again later.")),
+            Error::Schema { .. } => Some(String::from(
+                "The API may have changed, please report this at https://github.com/mWalrus/twitch-lookup/issues",
+            )),
+            _ => None,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use chrono::Duration;
 
-pub fn readable_number(num: u32) -> String {
-    let num = num.to_string();
+pub fn readable_number(num: impl Into<u64>) -> String {
+    let num = num.into().to_string();
     let mut readable_num = String::new();
     for (i, char) in num.chars().rev().enumerate() {
         if i % 3 == 0 && i != 0 {
@@ -29,11 +29,15 @@ pub fn duration_to_hms(d: Duration) -> String {
     result
 }
 
-pub fn hex_to_rgb(mut hex: &str) -> (u8, u8, u8) {
+/// Parses a `#rrggbb` color, `None` for anything else (users without a chat color have none).
+pub fn hex_to_rgb(mut hex: &str) -> Option<(u8, u8, u8)> {
     hex = hex.trim_start_matches('#');
-    let hex = u32::from_str_radix(hex, 16).unwrap();
+    if hex.len() != 6 {
+        return None;
+    }
+    let hex = u32::from_str_radix(hex, 16).ok()?;
     let r: u8 = (hex >> 16u8 & 0xFF) as u8;
     let g: u8 = (hex >> 8u8 & 0xFF) as u8;
     let b: u8 = hex as u8;
-    (r, g, b)
+    Some((r, g, b))
 }
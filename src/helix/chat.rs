@@ -0,0 +1,124 @@
+use crate::leppunen::modvip::ModVip;
+use serde::{Deserialize, Serialize};
+
+/// Someone in a chat as listed by Get Chatters.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Chatter {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+}
+
+/// Twitch's answer to Send Chat Message.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SentMessage {
+    /// Empty when the message was dropped.
+    pub message_id: String,
+    pub is_sent: bool,
+    pub drop_reason: Option<DropReason>,
+}
+
+/// Why Twitch did not deliver a message, e.g. `msg_duplicate` or `msg_rejected_automod`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DropReason {
+    pub code: String,
+    pub message: String,
+}
+
+/// The people in a channel's chat, grouped by role.
+#[derive(Serialize)]
+pub struct Chat {
+    chatter_count: u32,
+    chatters: Chatters,
+}
+
+#[derive(Serialize)]
+pub struct Chatters {
+    broadcaster: Vec<String>,
+    moderators: Vec<String>,
+    vips: Vec<String>,
+    viewers: Vec<String>,
+}
+
+impl Chat {
+    /// Sorts `chatters` into the broadcaster, the moderators and VIPs in `roles` and everyone
+    /// else, as Get Chatters does not say who holds which role.
+    pub fn new(broadcaster: &str, chatters: Vec<Chatter>, roles: &ModVip) -> Self {
+        let mut grouped = Chatters {
+            broadcaster: Vec::new(),
+            moderators: Vec::new(),
+            vips: Vec::new(),
+            viewers: Vec::new(),
+        };
+        let chatter_count = chatters.len() as u32;
+        for chatter in chatters {
+            let login = chatter.user_login;
+            let group = if login == broadcaster {
+                &mut grouped.broadcaster
+            } else if roles.mods.iter().any(|m| m.id == chatter.user_id) {
+                &mut grouped.moderators
+            } else if roles.vips.iter().any(|v| v.id == chatter.user_id) {
+                &mut grouped.vips
+            } else {
+                &mut grouped.viewers
+            };
+            group.push(login);
+        }
+        Self {
+            chatter_count,
+            chatters: grouped,
+        }
+    }
+
+    pub fn chatter_count(&self) -> u32 {
+        self.chatter_count
+    }
+
+    pub fn chatters(&self) -> &Chatters {
+        &self.chatters
+    }
+}
+
+impl Chatters {
+    pub fn broadcaster(&self) -> &str {
+        if self.broadcaster.is_empty() {
+            ""
+        } else {
+            &self.broadcaster[0]
+        }
+    }
+
+    pub fn moderators(&self) -> &Vec<String> {
+        &self.moderators
+    }
+
+    pub fn vips(&self) -> &Vec<String> {
+        &self.vips
+    }
+
+    pub fn viewers(&self) -> &Vec<String> {
+        &self.viewers
+    }
+
+    pub fn is_present(&self, user: &str) -> bool {
+        for moderator in &self.moderators {
+            if moderator == user {
+                return true;
+            }
+        }
+        for vip in &self.vips {
+            if vip == user {
+                return true;
+            }
+        }
+        for viewer in &self.viewers {
+            if viewer == user {
+                return true;
+            }
+        }
+        if let Some(br) = self.broadcaster.first() {
+            return br == user;
+        }
+        false
+    }
+}
@@ -1,112 +1,231 @@
 pub mod channel;
+#[cfg(feature = "chat")]
+pub mod chat;
 pub mod vod;
 
+use crate::cache::Ttl;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::http::Http;
 use crate::leppunen::Api;
-use crate::Config;
 use channel::Channel;
-use reqwest::{header, Client};
-use serde::Deserialize;
+#[cfg(feature = "chat")]
+use chat::{Chat, Chatter, SentMessage};
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use reqwest::{header, Url};
+use serde::{de::DeserializeOwned, Deserialize};
+#[cfg(feature = "chat")]
+use serde_json::json;
 use vod::Vod;
 
+/// Client for the Twitch Helix API, authenticated as the active profile.
 pub struct HelixClient {
-    client: Client,
+    http: Http,
+    url: String,
+    ivr: Api,
+    /// The account chatters are looked up as.
+    #[cfg(feature = "chat")]
+    login: String,
+    #[cfg(feature = "chat")]
+    user_id: String,
 }
 
+/// Helix never returns more than this many items per page.
+const MAX_PAGE_SIZE: usize = 100;
+/// Except for Get Chatters, which pages through large chats ten times faster.
+#[cfg(feature = "chat")]
+const MAX_CHATTERS_PAGE_SIZE: usize = 1000;
+
 #[derive(Deserialize)]
 pub struct HelixData<T> {
     data: Vec<T>,
+    #[serde(default)]
     pagination: Pagination,
 }
 
-#[derive(Deserialize, Clone)]
+#[derive(Deserialize, Default)]
 pub struct Pagination {
     cursor: Option<String>,
 }
 
-impl Pagination {
-    pub fn cursor(&self) -> Option<String> {
-        self.cursor.clone()
-    }
-}
-
-impl<T> HelixData<T>
-where
-    T: Clone,
-{
-    pub fn items(&self) -> Vec<T> {
-        self.data.clone()
-    }
-}
-
 impl HelixClient {
-    pub fn new(config: &Config) -> Self {
-        let builder = Client::builder();
+    pub fn new(config: &Config) -> Result<Self> {
         let mut headers = header::HeaderMap::new();
 
-        let bearer = format!("Bearer {}", config.access_token);
-        let auth_value = header::HeaderValue::from_str(&bearer).unwrap();
-        let client_id_value = header::HeaderValue::from_str(&config.client_id).unwrap();
+        let profile = config.profile();
+        let bearer = format!("Bearer {}", profile.access_token);
+        let malformed =
+            |_| Error::Unauthorized(String::from("The stored credentials are malformed"));
+        let auth_value = header::HeaderValue::from_str(&bearer).map_err(malformed)?;
+        let client_id_value =
+            header::HeaderValue::from_str(&profile.client_id).map_err(malformed)?;
 
         headers.insert(header::AUTHORIZATION, auth_value);
         headers.insert("Client-Id", client_id_value);
 
-        let client = builder.default_headers(headers).build().unwrap();
+        Ok(Self {
+            http: Http::new(config, "Twitch")
+                .with_headers(headers)
+                .for_account(format!("{}:{}", config.active_profile, profile.user_id)),
+            url: config.endpoints.helix(),
+            ivr: Api::new(config),
+            #[cfg(feature = "chat")]
+            login: profile.login.clone(),
+            #[cfg(feature = "chat")]
+            user_id: profile.user_id.clone(),
+        })
+    }
 
-        Self { client }
+    async fn get<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        what: &str,
+        ttl: Ttl,
+    ) -> Result<HelixData<T>> {
+        self.http.get(url, what, ttl).await?.json()
     }
 
-    pub async fn get_vods(&self, channel: &str, amount: Option<u8>) -> Option<Vec<Vod>> {
-        let user_id = Api::user(channel).await.unwrap().uid();
-        let res = self
-            .client
-            .get(format!(
-                "https://api.twitch.tv/helix/videos?user_id={user_id}&first={}",
-                amount.unwrap_or(1)
-            ))
-            .send()
-            .await
-            .unwrap()
-            .json::<HelixData<Vod>>()
-            .await
-            .unwrap();
-        Some(res.items())
+    /// Lazily walks the pages of a Helix list endpoint, yielding items as pages arrive.
+    /// `url` must already carry a query string, `limit` caps the total number of items.
+    pub fn paginate<T: DeserializeOwned>(
+        &self,
+        url: String,
+        limit: Option<usize>,
+        what: String,
+        ttl: Ttl,
+    ) -> impl Stream<Item = Result<T>> + '_ {
+        self.paginate_by(MAX_PAGE_SIZE, url, limit, what, ttl)
     }
 
-    pub async fn get_live_followed_channels(&self, user_id: &str) -> Option<Vec<Channel>> {
-        let url = format!("https://api.twitch.tv/helix/streams/followed?user_id={user_id}");
-        let res = self
-            .client
-            .get(url.clone())
-            .send()
-            .await
-            .unwrap()
-            .json::<HelixData<Channel>>()
-            .await
-            .unwrap();
-        // TODO: pagination
-        let mut items = res.items();
-        if let Some(c) = res.pagination.cursor() {
-            let mut new_url = url + &format!("after={c}");
-            loop {
-                let res = self
-                    .client
-                    .get(new_url.clone())
-                    .send()
-                    .await
-                    .unwrap()
-                    .json::<HelixData<Channel>>()
-                    .await
-                    .unwrap();
-                items.append(&mut res.items());
-                if let Some(new_c) = res.pagination.cursor() {
-                    new_url = format!(
-                        "https://api.twitch.tv/helix/streams/followed?user_id={user_id}&after={new_c}",
-                    );
-                } else {
-                    break;
+    /// [`Self::paginate`] for endpoints whose pages hold up to `page_size` items.
+    pub fn paginate_by<T: DeserializeOwned>(
+        &self,
+        page_size: usize,
+        url: String,
+        limit: Option<usize>,
+        what: String,
+        ttl: Ttl,
+    ) -> impl Stream<Item = Result<T>> + '_ {
+        let first = limit.map_or(page_size, |l| l.clamp(1, page_size));
+        // `None` once the last page was read, `Some(None)` before the first one
+        let pages = stream::try_unfold(Some(None), move |cursor: Option<Option<String>>| {
+            let url = url.clone();
+            let what = what.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok(None);
+                };
+                let mut page_url = Url::parse(&url)
+                    .map_err(|e| Error::Invalid(format!("Invalid Helix URL {url}: {e}")))?;
+                {
+                    // cursors are opaque and may hold `+`, `/` and `=`
+                    let mut query = page_url.query_pairs_mut();
+                    query.append_pair("first", &first.to_string());
+                    if let Some(after) = &cursor {
+                        query.append_pair("after", after);
+                    }
                 }
+                let page = self.get::<T>(page_url.as_str(), &what, ttl).await?;
+                let next = page.pagination.cursor.filter(|_| !page.data.is_empty());
+                Ok(Some((page.data, next.map(Some))))
             }
+        });
+        pages
+            .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+            .try_flatten()
+            .take(limit.unwrap_or(usize::MAX))
+    }
+
+    /// The latest `amount` (default 1) VODs of `channel`, newest first.
+    pub async fn get_vods(
+        &self,
+        channel: &str,
+        amount: Option<u8>,
+    ) -> Result<impl Stream<Item = Result<Vod>> + '_> {
+        let user = self.ivr.user(channel).await?;
+        if user.banned {
+            return Err(Error::Banned(user.login));
+        }
+        let url = format!("{}/videos?user_id={}", self.url, user.uid());
+        let what = format!("The VOD list of {channel}");
+        Ok(self.paginate(url, Some(amount.unwrap_or(1).into()), what, Ttl::Content))
+    }
+
+    /// The live channels `user_id` follows, by viewer count.
+    pub fn get_live_followed_channels(
+        &self,
+        user_id: &str,
+    ) -> impl Stream<Item = Result<Channel>> + '_ {
+        let url = format!("{}/streams/followed?user_id={user_id}", self.url);
+        let what = String::from("The followed channel list");
+        self.paginate(url, None, what, Ttl::Live)
+    }
+
+    /// Who is in `channel`'s chat, which Twitch only tells the broadcaster and moderators.
+    #[cfg(feature = "chat")]
+    pub async fn get_chat(&self, channel: &str) -> Result<Chat> {
+        let user = self.ivr.user(channel).await?;
+        if user.banned {
+            return Err(Error::Banned(user.login));
+        }
+        let url = format!(
+            "{}/chat/chatters?broadcaster_id={}&moderator_id={}",
+            self.url,
+            user.uid(),
+            self.user_id
+        );
+        let what = format!("The chatters of {channel}");
+        let chatters: Vec<Chatter> = self
+            .paginate_by(MAX_CHATTERS_PAGE_SIZE, url, None, what, Ttl::Live)
+            .try_collect()
+            .await
+            .map_err(|e| match e {
+                Error::Forbidden(_) => Error::Forbidden(format!(
+                    "{} is not a moderator of {channel}, Twitch only lists chatters to moderators",
+                    self.login
+                )),
+                e => e,
+            })?;
+        let roles = self.ivr.modvip(channel).await?;
+        Ok(Chat::new(&user.login, chatters, &roles))
+    }
+
+    /// Sends `message` to `channel`'s chat as the active profile, as a reply when `reply_to`
+    /// holds a message ID. A message Twitch drops is reported as [`Error::Dropped`].
+    #[cfg(feature = "chat")]
+    pub async fn send_chat_message(
+        &self,
+        channel: &str,
+        message: &str,
+        reply_to: Option<&str>,
+    ) -> Result<SentMessage> {
+        let user = self.ivr.user(channel).await?;
+        if user.banned {
+            return Err(Error::Banned(user.login));
+        }
+        let mut body = json!({
+            "broadcaster_id": user.uid(),
+            "sender_id": self.user_id,
+            "message": message,
+        });
+        if let Some(parent) = reply_to {
+            body["reply_parent_message_id"] = json!(parent);
+        }
+        let url = format!("{}/chat/messages", self.url);
+        let what = format!("Chatting in {channel}");
+        let sent: HelixData<SentMessage> = self.http.post(&url, &what, &body).await?.json()?;
+        let sent = sent.data.into_iter().next().ok_or_else(|| {
+            Error::Dropped(String::from(
+                "Twitch did not say whether the message was sent",
+            ))
+        })?;
+        if !sent.is_sent {
+            let reason = sent.drop_reason.as_ref().map_or_else(
+                || String::from("Twitch dropped the message"),
+                |reason| format!("{} ({})", reason.message, reason.code),
+            );
+            return Err(Error::Dropped(reason));
         }
-        Some(res.items())
+        Ok(sent)
     }
 }
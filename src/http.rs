@@ -0,0 +1,379 @@
+use crate::cache::{Cache, Ttl};
+use crate::config::{CacheMode, Config};
+use crate::error::{Error, Result};
+use crate::recording::{self, Exchange, Recording};
+use crate::stub::Stub;
+use reqwest::header::{self, HeaderMap};
+use reqwest::{Client, Method, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, field, trace, Span};
+
+const USER_AGENT: &str = concat!(
+    "twitch-lookup/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/mWalrus/twitch-lookup)"
+);
+/// Waits longer than this are reported as [`Error::RateLimited`] instead of slept through.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+const BACKOFF: Duration = Duration::from_millis(500);
+
+static CLIENT: OnceLock<Client> = OnceLock::new();
+/// When each service that ran out of its rate limit budget accepts requests again.
+static RESETS: Mutex<BTreeMap<&'static str, SystemTime>> = Mutex::new(BTreeMap::new());
+static DRY_RUN: Mutex<DryRun> = Mutex::new(DryRun {
+    requests: Vec::new(),
+    stubbed: false,
+});
+
+/// What `--dry-run` kept from being sent.
+#[derive(Debug, Default)]
+pub struct DryRun {
+    /// The requests in the order the command made them, e.g.
+    /// `GET https://api.ivr.fi/v2/twitch/user/forsen`, with `(cached)` after the ones the cache
+    /// answered.
+    pub requests: Vec<String>,
+    /// Whether the command went on with a made-up answer, which anything it did after that
+    /// is based on.
+    pub stubbed: bool,
+}
+
+/// Notes a request `--dry-run` did not send.
+pub fn would_send(request: String) {
+    DRY_RUN.lock().unwrap().requests.push(request);
+}
+
+/// Everything noted by the `--dry-run` so far.
+pub fn dry_run() -> DryRun {
+    std::mem::take(&mut *DRY_RUN.lock().unwrap())
+}
+
+/// The client every request goes through, built once from the `[http]` section of the config.
+pub fn client(config: &Config) -> Client {
+    CLIENT
+        .get_or_init(|| {
+            Client::builder()
+                .user_agent(USER_AGENT)
+                .timeout(Duration::from_secs(config.http.timeout))
+                .connect_timeout(Duration::from_secs(config.http.timeout.min(10)))
+                .build()
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+/// A successful response with its body already read.
+pub struct Response {
+    service: &'static str,
+    pub body: String,
+    /// Set on the empty responses `--dry-run` answers with instead of sending the request.
+    stub: bool,
+}
+
+impl Response {
+    fn new(service: &'static str, body: String) -> Self {
+        Self {
+            service,
+            body,
+            stub: false,
+        }
+    }
+
+    fn stub(service: &'static str) -> Self {
+        DRY_RUN.lock().unwrap().stubbed = true;
+        Self {
+            service,
+            body: String::new(),
+            stub: true,
+        }
+    }
+
+    /// Reads the body as JSON, a body that does not match `T` is reported as [`Error::Schema`].
+    /// A `--dry-run` stub reads as a made-up `T`, see [`Stub`].
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        let value = if self.stub {
+            T::deserialize(Stub::new())
+        } else {
+            serde_json::from_str(&self.body)
+        };
+        value.map_err(|source| Error::Schema {
+            service: self.service,
+            source,
+        })
+    }
+}
+
+/// Requests to one service, GETs retried with backoff and paced by its rate limit headers.
+#[derive(Clone)]
+pub struct Http {
+    client: Client,
+    service: &'static str,
+    headers: HeaderMap,
+    retries: u32,
+    cache: Option<Cache>,
+    offline: bool,
+    dry_run: bool,
+    recording: Recording,
+}
+
+impl Http {
+    pub fn new(config: &Config, service: &'static str) -> Self {
+        Self {
+            client: client(config),
+            service,
+            headers: HeaderMap::new(),
+            retries: config.http.retries,
+            cache: Cache::new(config).ok(),
+            offline: config.cache.mode == CacheMode::Offline,
+            dry_run: config.http.dry_run,
+            recording: config.http.recording.clone(),
+        }
+    }
+
+    /// Sends `headers` along with every request.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Caches the answers for `account` only, for requests made with its token.
+    pub fn for_account(mut self, account: String) -> Self {
+        self.cache = self.cache.map(|cache| cache.for_account(account));
+        self
+    }
+
+    /// GETs `url` or reuses a cached body younger than `ttl`, a 404 becomes
+    /// [`Error::NotFound`] for `what`. A `--dry-run` notes the request and answers with a stub
+    /// when it is not cached.
+    #[tracing::instrument(
+        name = "request",
+        skip_all,
+        fields(
+            service = self.service,
+            method = "GET",
+            url = %url,
+            cache = field::Empty,
+            status = field::Empty,
+            latency_ms = field::Empty,
+            retries = field::Empty,
+        )
+    )]
+    pub async fn get(&self, url: &str, what: &str, ttl: Ttl) -> Result<Response> {
+        let service = self.service;
+        let cached = self.cache.as_ref().and_then(|c| c.get(service, url, ttl));
+        if self.dry_run {
+            let note = if cached.is_some() { " (cached)" } else { "" };
+            would_send(format!("GET {url}{note}"));
+        }
+        if let Some(body) = cached {
+            Span::current().record("cache", "hit");
+            debug!("answered from the cache");
+            return Ok(Response::new(service, body));
+        }
+        Span::current().record("cache", "miss");
+        if self.offline {
+            return Err(Error::NotCached(what.to_string()));
+        }
+        if self.dry_run {
+            return Ok(Response::stub(service));
+        }
+        let res = self.get_with_retries(url, what).await?;
+        if let Some(cache) = &self.cache {
+            cache.put(service, url, ttl, &res.body);
+        }
+        Ok(res)
+    }
+
+    async fn get_with_retries(&self, url: &str, what: &str) -> Result<Response> {
+        let mut attempt = 0;
+        loop {
+            wait_for_reset(self.service).await?;
+            Span::current().record("retries", attempt);
+            let err = match self.try_request(Method::GET, url, what, None).await {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            };
+            let delay = match &err {
+                Error::RateLimited {
+                    retry_after: Some(secs),
+                    ..
+                } if Duration::from_secs(*secs) <= MAX_WAIT => Duration::from_secs(*secs),
+                Error::RateLimited {
+                    retry_after: None, ..
+                }
+                | Error::Network { .. } => BACKOFF * 2u32.pow(attempt),
+                Error::Upstream { status, .. } if status.is_server_error() => {
+                    BACKOFF * 2u32.pow(attempt)
+                }
+                _ => return Err(err),
+            };
+            if attempt >= self.retries {
+                return Err(err);
+            }
+            attempt += 1;
+            debug!(attempt, delay_ms = delay.as_millis() as u64, error = %err, "retrying");
+            // a replayed retry is answered by the next recording, there is nothing to wait for
+            if !matches!(self.recording, Recording::Replay(_)) {
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+
+    /// POSTs `json` to `url` once, as a retry could do whatever the request does twice.
+    #[tracing::instrument(
+        name = "request",
+        skip_all,
+        fields(
+            service = self.service,
+            method = "POST",
+            url = %url,
+            status = field::Empty,
+            latency_ms = field::Empty,
+        )
+    )]
+    pub async fn post<T: Serialize>(&self, url: &str, what: &str, json: &T) -> Result<Response> {
+        let body = serde_json::to_string(json).expect("request bodies serialize");
+        if self.dry_run {
+            would_send(format!("POST {url} {body}"));
+            return Ok(Response::stub(self.service));
+        }
+        wait_for_reset(self.service).await?;
+        self.try_request(Method::POST, url, what, Some(body)).await
+    }
+
+    async fn try_request(
+        &self,
+        method: Method,
+        url: &str,
+        what: &str,
+        json: Option<String>,
+    ) -> Result<Response> {
+        let service = self.service;
+        let started = Instant::now();
+        let (status, headers, body) = match &self.recording {
+            Recording::Replay(dir) => {
+                let exchange = recording::replay(dir, service, method.as_str(), url)?;
+                (
+                    exchange.status(),
+                    exchange.response_headers(),
+                    exchange.body,
+                )
+            }
+            _ => self.send(method.clone(), url, json).await?,
+        };
+        if let Recording::Record(dir) = &self.recording {
+            let exchange = Exchange::new(
+                service,
+                method.as_str(),
+                url,
+                &self.headers,
+                status,
+                &headers,
+                &body,
+            );
+            recording::record(dir, &exchange);
+        }
+        let span = Span::current();
+        span.record("status", status.as_u16());
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+        debug!(bytes = body.len(), "response");
+        trace!(%body);
+        track_rate_limit(service, &headers);
+        match status {
+            StatusCode::UNAUTHORIZED => {
+                return Err(Error::Unauthorized(format!(
+                    "{service} rejected your token"
+                )))
+            }
+            StatusCode::FORBIDDEN => {
+                return Err(Error::Forbidden(format!(
+                    "{what} is not available to your account"
+                )))
+            }
+            StatusCode::NOT_FOUND => return Err(Error::NotFound(what.to_string())),
+            StatusCode::TOO_MANY_REQUESTS => {
+                return Err(Error::RateLimited {
+                    service,
+                    retry_after: retry_after(&headers),
+                })
+            }
+            status if status.is_client_error() || status.is_server_error() => {
+                return Err(Error::Upstream { service, status })
+            }
+            _ => {}
+        }
+        Ok(Response::new(service, body))
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        json: Option<String>,
+    ) -> Result<(StatusCode, HeaderMap, String)> {
+        let service = self.service;
+        let network = |source| Error::Network { service, source };
+        let mut request = self
+            .client
+            .request(method, url)
+            .headers(self.headers.clone());
+        if let Some(json) = json {
+            request = request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(json);
+        }
+        let res = request.send().await.map_err(network)?;
+        let (status, headers) = (res.status(), res.headers().clone());
+        let body = res.text().await.map_err(network)?;
+        Ok((status, headers, body))
+    }
+}
+
+/// Remembers when a service's budget resets once Helix reports `Ratelimit-Remaining: 0`.
+fn track_rate_limit(service: &'static str, headers: &HeaderMap) {
+    if header(headers, "ratelimit-remaining") != Some(0) {
+        return;
+    }
+    if let Some(reset) = header(headers, "ratelimit-reset") {
+        let reset = UNIX_EPOCH + Duration::from_secs(reset);
+        RESETS.lock().unwrap().insert(service, reset);
+    }
+}
+
+async fn wait_for_reset(service: &'static str) -> Result<()> {
+    let Some(reset) = RESETS.lock().unwrap().remove(service) else {
+        return Ok(());
+    };
+    let Ok(wait) = reset.duration_since(SystemTime::now()) else {
+        return Ok(());
+    };
+    debug!(
+        wait_ms = wait.as_millis() as u64,
+        "waiting for the rate limit to reset"
+    );
+    if wait > MAX_WAIT {
+        return Err(Error::RateLimited {
+            service,
+            retry_after: Some(wait.as_secs()),
+        });
+    }
+    tokio::time::sleep(wait).await;
+    Ok(())
+}
+
+/// Seconds until a rate limit resets, from `Retry-After` or Twitch's `Ratelimit-Reset` timestamp.
+fn retry_after(headers: &HeaderMap) -> Option<u64> {
+    if let Some(secs) = header(headers, header::RETRY_AFTER.as_str()) {
+        return Some(secs);
+    }
+    let reset = header(headers, "ratelimit-reset")?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    Some(reset.saturating_sub(now))
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<u64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
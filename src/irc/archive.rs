@@ -0,0 +1,211 @@
+use super::message::Message;
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The commands worth keeping for moderation, everything else the server sends is dropped.
+const ARCHIVED: [&str; 4] = ["PRIVMSG", "USERNOTICE", "CLEARCHAT", "CLEARMSG"];
+/// Extensions of the two logs kept for each channel and day.
+const FORMATS: [&str; 2] = ["irc", "jsonl"];
+
+/// A chat event as written to the JSONL logs.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatEvent {
+    pub channel: String,
+    /// The IRC command in lowercase: `privmsg`, `usernotice`, `clearchat` or `clearmsg`.
+    pub kind: String,
+    /// The sender, or the user who was timed out, banned or had a message deleted. `None`
+    /// when the whole chat was cleared.
+    pub login: Option<String>,
+    /// The message sent, shared with a sub or deleted.
+    pub text: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub sent_at: DateTime<Utc>,
+}
+
+impl ChatEvent {
+    /// Reads one of the archived commands, `None` for anything else.
+    pub fn from_irc(msg: &Message) -> Option<Self> {
+        if !ARCHIVED.contains(&msg.command.as_str()) {
+            return None;
+        }
+        let channel = msg.params.first()?.trim_start_matches('#').to_string();
+        let login = match msg.command.as_str() {
+            "PRIVMSG" => msg.nick(),
+            "CLEARCHAT" => msg.params.get(1).map(String::as_str),
+            _ => msg.tag("login"),
+        };
+        let sent_at = msg
+            .tag("tmi-sent-ts")
+            .and_then(|ts| ts.parse().ok())
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+            .unwrap_or_else(Utc::now);
+        Some(Self {
+            channel,
+            kind: msg.command.to_lowercase(),
+            login: login.map(String::from),
+            text: msg
+                .params
+                .get(1)
+                .filter(|_| msg.command != "CLEARCHAT")
+                .cloned(),
+            tags: msg.tags.clone(),
+            sent_at,
+        })
+    }
+}
+
+/// Chat logs in `<dir>/<channel>/<YYYY-MM-DD>.irc` and `.jsonl`, one line per event, with the
+/// day taken from Twitch's timestamp in UTC. Logs of earlier days are gzipped, and deleted
+/// after `keep_days` if that is not 0. Lines written to a day that was already gzipped end up
+/// in another gzip member of the same file.
+pub struct Archive {
+    dir: PathBuf,
+    keep_days: u32,
+    days: HashMap<String, Day>,
+}
+
+/// The logs a channel is currently written to.
+struct Day {
+    date: NaiveDate,
+    files: [File; 2],
+}
+
+impl Archive {
+    /// Opens the archive in `dir`, tidying up what earlier runs left behind first. Logs of
+    /// today are appended to, so a restarted recorder picks up where it stopped.
+    pub fn open(dir: &Path, keep_days: u32) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+        let archive = Self {
+            dir: dir.to_path_buf(),
+            keep_days,
+            days: HashMap::new(),
+        };
+        archive.tidy(Utc::now().naive_utc().date())?;
+        Ok(archive)
+    }
+
+    /// Writes `msg` to its channel's logs, returning whether it is an archived command.
+    pub fn write(&mut self, msg: &Message) -> Result<bool> {
+        let Some(event) = ChatEvent::from_irc(msg) else {
+            return Ok(false);
+        };
+        let date = event.sent_at.naive_utc().date();
+        let rotated = match self.days.get(&event.channel) {
+            // late messages stay in the current day instead of reopening a finished one
+            Some(day) => date > day.date,
+            None => true,
+        };
+        if rotated {
+            let day = Day::open(&self.dir.join(&event.channel), date)?;
+            if self.days.insert(event.channel.clone(), day).is_some() {
+                self.tidy(date)?;
+            }
+        }
+        let day = &mut self.days.get_mut(&event.channel).unwrap();
+        let json = serde_json::to_string(&event)?;
+        for (file, line) in day.files.iter_mut().zip([msg.raw.as_str(), json.as_str()]) {
+            writeln!(file, "{line}")
+                .and_then(|_| file.flush())
+                .context("Could not write the chat log")?;
+        }
+        Ok(true)
+    }
+
+    /// Closes the logs of channels that did not chat since the day changed to `today`, and
+    /// compresses them along with the rest of the earlier logs.
+    pub fn rotate(&mut self, today: NaiveDate) -> Result<()> {
+        self.days.retain(|_, day| day.date >= today);
+        self.tidy(today)
+    }
+
+    /// Compresses the logs from before `today` and deletes the ones older than `keep_days`,
+    /// leaving alone the ones still written to.
+    fn tidy(&self, today: NaiveDate) -> Result<()> {
+        for channel in subdirs(&self.dir)? {
+            let open = channel
+                .file_name()
+                .and_then(|name| self.days.get(name.to_str()?))
+                .map(|day| day.date);
+            for entry in fs::read_dir(&channel)?.flatten() {
+                let path = entry.path();
+                let Some(date) = log_date(&path).filter(|date| Some(*date) != open) else {
+                    continue;
+                };
+                let age = (today - date).num_days();
+                if self.keep_days > 0 && age > i64::from(self.keep_days) {
+                    fs::remove_file(&path)?;
+                } else if age > 0 && path.extension().is_some_and(|ext| ext != "gz") {
+                    compress(&path)
+                        .with_context(|| format!("Could not compress {}", path.display()))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Day {
+    fn open(dir: &Path, date: NaiveDate) -> Result<Self> {
+        fs::create_dir_all(dir).with_context(|| format!("Could not create {}", dir.display()))?;
+        let open = |ext: &str| {
+            let path = dir.join(format!("{date}.{ext}"));
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .with_context(|| format!("Could not open {}", path.display()))
+        };
+        Ok(Self {
+            date,
+            files: [open(FORMATS[0])?, open(FORMATS[1])?],
+        })
+    }
+}
+
+/// The day of a log file named like `2022-03-15.irc` or `2022-03-15.jsonl.gz`.
+fn log_date(path: &Path) -> Option<NaiveDate> {
+    let name = path.file_name()?.to_str()?;
+    let (date, ext) = name.split_once('.')?;
+    let ext = ext.strip_suffix(".gz").unwrap_or(ext);
+    FORMATS
+        .contains(&ext)
+        .then(|| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+        .flatten()
+}
+
+/// How long until the day changes in UTC, which is when [`Archive::rotate`] is due.
+pub fn until_next_day(now: DateTime<Utc>) -> std::time::Duration {
+    let now = now.naive_utc();
+    let midnight = now.date().succ().and_hms(0, 0, 0);
+    (midnight - now).to_std().unwrap_or_default()
+}
+
+/// Replaces `path` with a gzipped copy named `<path>.gz`, appended as another gzip member if
+/// that day was compressed before.
+fn compress(path: &Path) -> io::Result<()> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let gz = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&gz_path)?;
+    let mut encoder = GzEncoder::new(gz, Compression::default());
+    io::copy(&mut File::open(path)?, &mut encoder)?;
+    encoder.finish()?;
+    fs::remove_file(path)
+}
+
+fn subdirs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    Ok(fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect())
+}
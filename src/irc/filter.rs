@@ -0,0 +1,118 @@
+use super::ChatMessage;
+use crate::config::ChatConfig;
+use crate::error::{Error, Result};
+use regex::Regex;
+
+/// Badges whose holders count as moderators for `--mods-only`.
+const MOD_BADGES: [&str; 3] = ["broadcaster", "lead_moderator", "moderator"];
+
+/// Which chat messages are shown and which of them are highlighted. The ignore and highlight
+/// rules from the config always apply, the rest is set per run from the `chat tail` flags.
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    /// Only messages from these logins, every sender when empty.
+    pub users: Vec<String>,
+    /// Only messages whose text matches.
+    pub grep: Option<Regex>,
+    pub exclude_bots: bool,
+    /// Only messages from the broadcaster and moderators.
+    pub mods_only: bool,
+    /// Only messages that mention this login.
+    pub mentions: Option<String>,
+    /// Messages mentioning this login are highlighted, usually the logged in account.
+    pub login: Option<String>,
+    highlights: Vec<Regex>,
+    ignore_users: Vec<String>,
+    ignore: Vec<Regex>,
+    bots: Vec<String>,
+}
+
+impl ChatFilter {
+    /// A filter with the rules of the `[chat]` section, showing every message they do not ignore.
+    pub fn new(config: &ChatConfig) -> Result<Self> {
+        let mut filter = Self {
+            ignore_users: lowercase(&config.ignore_users),
+            bots: lowercase(&config.bots),
+            ignore: config
+                .ignore
+                .iter()
+                .map(|pattern| regex(pattern, &format!("`{pattern}` in the [chat] ignore list")))
+                .collect::<Result<_>>()?,
+            ..Self::default()
+        };
+        for word in &config.highlights {
+            filter.highlight(word);
+        }
+        Ok(filter)
+    }
+
+    /// Highlights messages containing `word`, compared as a whole word regardless of case.
+    pub fn highlight(&mut self, word: &str) {
+        self.highlights.push(word_pattern(word));
+    }
+
+    /// Only shows messages whose text matches `pattern`.
+    pub fn grep(&mut self, pattern: &str) -> Result<()> {
+        self.grep = Some(regex(pattern, "--grep")?);
+        Ok(())
+    }
+
+    /// Whether `message` passes every rule.
+    pub fn accepts(&self, message: &ChatMessage) -> bool {
+        let login = message.login.to_lowercase();
+        if self.ignore_users.contains(&login)
+            || self.ignore.iter().any(|re| re.is_match(&message.text))
+        {
+            return false;
+        }
+        if !self.users.is_empty() && !self.users.iter().any(|u| u.eq_ignore_ascii_case(&login)) {
+            return false;
+        }
+        if self.exclude_bots && self.bots.contains(&login) {
+            return false;
+        }
+        if self.mods_only
+            && !message
+                .badges
+                .iter()
+                .any(|b| MOD_BADGES.contains(&b.as_str()))
+        {
+            return false;
+        }
+        if let Some(mentioned) = &self.mentions {
+            if !mentions(message, mentioned) {
+                return false;
+            }
+        }
+        self.grep
+            .as_ref()
+            .is_none_or(|re| re.is_match(&message.text))
+    }
+
+    /// Whether `message` mentions the account or contains a highlighted word.
+    pub fn highlights(&self, message: &ChatMessage) -> bool {
+        self.login
+            .as_deref()
+            .is_some_and(|login| mentions(message, login))
+            || self.highlights.iter().any(|re| re.is_match(&message.text))
+    }
+}
+
+fn mentions(message: &ChatMessage, login: &str) -> bool {
+    !login.is_empty() && word_pattern(login).is_match(&message.text)
+}
+
+/// Matches `word` on its own, e.g. `forsen` in `@forsen hi` but not in `forsenE`.
+fn word_pattern(word: &str) -> Regex {
+    let escaped = regex::escape(word.trim());
+    // an escaped literal always compiles
+    Regex::new(&format!(r"(?i)(^|\W){escaped}($|\W)")).unwrap()
+}
+
+fn regex(pattern: &str, what: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| Error::Invalid(format!("{what} is not a valid regex: {e}")))
+}
+
+fn lowercase(logins: &[String]) -> Vec<String> {
+    logins.iter().map(|login| login.to_lowercase()).collect()
+}
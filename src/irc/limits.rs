@@ -0,0 +1,110 @@
+use crate::error::Error;
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Seconds Twitch counts messages over, for both the rate limit and duplicates.
+const WINDOW: i64 = 30;
+/// Messages a user may send per window.
+const USER_LIMIT: usize = 20;
+/// Messages a user may send per window in channels where they are the broadcaster, a
+/// moderator or a VIP.
+const PRIVILEGED_LIMIT: usize = 100;
+/// The longest message Twitch accepts, in characters.
+const MAX_LENGTH: usize = 500;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Sent {
+    login: String,
+    channel: String,
+    text: String,
+    sent_at: DateTime<Utc>,
+}
+
+/// The chat messages sent in the last 30 seconds, kept in the cache directory so Twitch's
+/// limits are respected across runs instead of having messages silently dropped.
+pub struct SentLog {
+    path: PathBuf,
+    sent: Vec<Sent>,
+}
+
+impl SentLog {
+    pub fn load() -> Result<Self> {
+        let path = dirs::cache_dir()
+            .ok_or_else(|| anyhow!("Could not find the cache directory"))?
+            .join("twitch-lookup/sent.json");
+        let sent: Vec<Sent> = fs::read(&path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default();
+        let cutoff = Utc::now() - Duration::seconds(WINDOW);
+        Ok(Self {
+            path,
+            sent: sent.into_iter().filter(|s| s.sent_at > cutoff).collect(),
+        })
+    }
+
+    /// Refuses a message Twitch would not deliver: one [`validate`] refuses, one that
+    /// repeats the last message `login` sent to `channel` unless they are `privileged` there,
+    /// or one over the rate limit.
+    pub fn check(&self, login: &str, channel: &str, text: &str, privileged: bool) -> Result<()> {
+        validate(text)?;
+        let mine: Vec<&Sent> = self.sent.iter().filter(|s| s.login == login).collect();
+        let duplicate = mine
+            .iter()
+            .rev()
+            .find(|s| s.channel == channel)
+            .is_some_and(|s| s.text == text);
+        if duplicate && !privileged {
+            bail!(Error::Dropped(format!(
+                "You sent the same message to {channel} less than {WINDOW} seconds ago, \
+                 Twitch drops it as a duplicate"
+            )));
+        }
+        let limit = if privileged {
+            PRIVILEGED_LIMIT
+        } else {
+            USER_LIMIT
+        };
+        if mine.len() >= limit {
+            let oldest = mine[mine.len() - limit].sent_at;
+            let wait = oldest + Duration::seconds(WINDOW) - Utc::now();
+            bail!(Error::RateLimited {
+                service: "Twitch chat",
+                retry_after: Some(wait.num_seconds().max(1) as u64),
+            });
+        }
+        Ok(())
+    }
+
+    /// Remembers a message that was sent.
+    pub fn push(&mut self, login: &str, channel: &str, text: &str) -> Result<()> {
+        self.sent.push(Sent {
+            login: login.to_string(),
+            channel: channel.to_string(),
+            text: text.to_string(),
+            sent_at: Utc::now(),
+        });
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, serde_json::to_vec(&self.sent)?)?;
+        Ok(())
+    }
+}
+
+/// Refuses a message that is empty or too long, which needs nothing but the message.
+pub fn validate(text: &str) -> Result<()> {
+    if text.trim().is_empty() {
+        bail!(Error::Invalid(String::from("The message is empty")));
+    }
+    let length = text.chars().count();
+    if length > MAX_LENGTH {
+        bail!(Error::Invalid(format!(
+            "The message is {length} characters long, Twitch takes at most {MAX_LENGTH}"
+        )));
+    }
+    Ok(())
+}
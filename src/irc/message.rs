@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+
+/// One line of IRC with its IRCv3 tags, e.g.
+/// `@color=#FF0000;display-name=forsen :forsen!forsen@forsen.tmi.twitch.tv PRIVMSG #forsen :hi`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// The line as it was received.
+    pub raw: String,
+    pub tags: BTreeMap<String, String>,
+    pub prefix: Option<String>,
+    pub command: String,
+    pub params: Vec<String>,
+}
+
+impl Message {
+    /// Parses a line without its trailing `\r\n`, `None` if it has no command.
+    pub fn parse(line: &str) -> Option<Self> {
+        let raw = line.trim_end_matches(['\r', '\n']);
+        let mut rest = raw;
+        let mut tags = BTreeMap::new();
+        if let Some(tagged) = rest.strip_prefix('@') {
+            let (raw, after) = tagged.split_once(' ')?;
+            for tag in raw.split(';') {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                tags.insert(key.to_string(), unescape(value));
+            }
+            rest = after.trim_start();
+        }
+        let mut prefix = None;
+        if let Some(prefixed) = rest.strip_prefix(':') {
+            let (raw, after) = prefixed.split_once(' ')?;
+            prefix = Some(raw.to_string());
+            rest = after.trim_start();
+        }
+        let (middle, trailing) = match rest.split_once(" :") {
+            Some((middle, trailing)) => (middle, Some(trailing)),
+            None => (rest, None),
+        };
+        let mut words = middle.split(' ').filter(|w| !w.is_empty());
+        let command = words.next()?.to_string();
+        let mut params: Vec<String> = words.map(String::from).collect();
+        params.extend(trailing.map(String::from));
+        Some(Self {
+            raw: raw.to_string(),
+            tags,
+            prefix,
+            command,
+            params,
+        })
+    }
+
+    /// The nickname in the prefix, `forsen` in `forsen!forsen@forsen.tmi.twitch.tv`.
+    pub fn nick(&self) -> Option<&str> {
+        let prefix = self.prefix.as_deref()?;
+        Some(prefix.split_once('!').map_or(prefix, |(nick, _)| nick))
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .get(key)
+            .map(String::as_str)
+            .filter(|v| !v.is_empty())
+    }
+}
+
+/// Escapes a tag value the way [`unescape`] reads it back.
+pub(crate) fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            ';' => out.push_str("\\:"),
+            ' ' => out.push_str("\\s"),
+            '\r' => out.push_str("\\r"),
+            '\n' => out.push_str("\\n"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Undoes the escaping of IRCv3 tag values.
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':') => out.push(';'),
+            Some('s') => out.push(' '),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => {}
+        }
+    }
+    out
+}
@@ -0,0 +1,406 @@
+pub mod archive;
+pub mod filter;
+pub mod limits;
+pub mod message;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use futures::stream::{self, Stream};
+use futures::{future, SinkExt, StreamExt, TryStreamExt};
+use message::Message;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, warn};
+
+const SERVICE: &str = "Twitch chat";
+/// Twitch pings every five minutes, a connection silent for longer than this is dead.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(6 * 60);
+/// How long Twitch gets to confirm a JOIN or a sent message.
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+const BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// A message sent to a channel's chat.
+#[derive(Serialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub channel: String,
+    pub login: String,
+    pub display_name: String,
+    /// The sender's name color as `#RRGGBB`, empty when they never picked one.
+    pub color: String,
+    /// Badge names like `moderator` or `subscriber`, in the order Twitch shows them.
+    pub badges: Vec<String>,
+    pub text: String,
+    /// Whether the message was sent with `/me`.
+    pub action: bool,
+    pub sent_at: DateTime<Utc>,
+    /// Set for messages a [`filter::ChatFilter`] highlights.
+    pub highlighted: bool,
+}
+
+impl ChatMessage {
+    /// Reads a `PRIVMSG`, `None` for any other message.
+    pub fn from_irc(msg: &Message) -> Option<Self> {
+        if msg.command != "PRIVMSG" || msg.params.len() < 2 {
+            return None;
+        }
+        let login = msg.nick()?.to_string();
+        let raw = &msg.params[1];
+        let (text, action) = match raw
+            .strip_prefix("\u{1}ACTION ")
+            .map(|t| t.trim_end_matches('\u{1}'))
+        {
+            Some(text) => (text.to_string(), true),
+            None => (raw.clone(), false),
+        };
+        let sent_at = msg
+            .tag("tmi-sent-ts")
+            .and_then(|ts| ts.parse().ok())
+            .and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+            .unwrap_or_else(Utc::now);
+        Some(Self {
+            channel: msg.params[0].trim_start_matches('#').to_string(),
+            display_name: msg.tag("display-name").unwrap_or(&login).to_string(),
+            login,
+            color: msg.tag("color").unwrap_or_default().to_string(),
+            badges: msg
+                .tag("badges")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|badge| badge.split('/').next())
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+            text,
+            action,
+            sent_at,
+            highlighted: false,
+        })
+    }
+
+    /// Starts the shell command `hook` with the message in `TL_CHAT_CHANNEL`, `TL_CHAT_LOGIN`,
+    /// `TL_CHAT_DISPLAY_NAME` and `TL_CHAT_TEXT`, without waiting for it to finish. A task
+    /// collects its exit status, so finished hooks do not linger as zombies.
+    pub fn run_hook(&self, hook: &str) -> io::Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command
+            .arg(hook)
+            .env("TL_CHAT_CHANNEL", &self.channel)
+            .env("TL_CHAT_LOGIN", &self.login)
+            .env("TL_CHAT_DISPLAY_NAME", &self.display_name)
+            .env("TL_CHAT_TEXT", &self.text);
+        let mut child = command.spawn()?;
+        let hook = hook.to_string();
+        tokio::spawn(async move {
+            match child.wait().await {
+                Ok(status) if !status.success() => debug!(%status, hook, "chat hook failed"),
+                Ok(_) => {}
+                Err(e) => debug!(hook, "could not wait for the chat hook: {e}"),
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Client for Twitch chat over IRC on a WebSocket, reading as the active profile when its
+/// token has the `chat:read` scope and anonymously otherwise.
+pub struct IrcClient {
+    url: String,
+    /// Login and access token, `None` to read anonymously.
+    credentials: Option<(String, String)>,
+    /// Login and access token when the token has the `chat:edit` scope.
+    sender: Option<(String, String)>,
+    retries: u32,
+}
+
+/// The connection behind [`IrcClient::tail`] and the lines it has yet to hand out.
+struct Session {
+    socket: Option<Socket>,
+    pending: VecDeque<String>,
+    failures: u32,
+}
+
+impl IrcClient {
+    pub fn new(config: &Config) -> Self {
+        let profile = config.profile();
+        let with_scope = |scope: &str| {
+            (profile.is_logged_in() && profile.scopes.iter().any(|s| s == scope))
+                .then(|| (profile.login.clone(), profile.access_token.clone()))
+        };
+        Self {
+            url: config.endpoints.irc(),
+            credentials: with_scope("chat:read"),
+            sender: with_scope("chat:edit"),
+            retries: config.http.retries,
+        }
+    }
+
+    /// Keeps reconnecting however often the connection drops, for clients that run unattended.
+    pub fn keep_reconnecting(mut self) -> Self {
+        self.retries = u32::MAX;
+        self
+    }
+
+    /// Joins `channel` and yields its chat messages as they arrive.
+    pub fn tail(&self, channel: &str) -> impl Stream<Item = Result<ChatMessage>> + '_ {
+        self.messages(&[channel])
+            .try_filter_map(|msg| future::ready(Ok(ChatMessage::from_irc(&msg))))
+    }
+
+    /// Joins `channels` and yields everything the server sends as it arrives, except what the
+    /// client answers itself. Dropped connections are reestablished with backoff, giving up
+    /// after `retries` failures in a row, and the client reconnects right away when Twitch
+    /// announces a restart.
+    pub fn messages(&self, channels: &[&str]) -> impl Stream<Item = Result<Message>> + '_ {
+        let channels = channels
+            .iter()
+            .map(|channel| format!("#{}", channel.trim_start_matches('#').to_lowercase()))
+            .collect::<Vec<_>>()
+            .join(",");
+        let session = Session {
+            socket: None,
+            pending: VecDeque::new(),
+            failures: 0,
+        };
+        stream::try_unfold(session, move |mut session| {
+            let channels = channels.clone();
+            async move {
+                loop {
+                    if let Some(line) = session.pending.pop_front() {
+                        if let Some(msg) = self.handle(&mut session, &line).await? {
+                            return Ok(Some((msg, session)));
+                        }
+                        continue;
+                    }
+                    let Some(socket) = session.socket.as_mut() else {
+                        match self.connect(&channels, self.credentials.as_ref()).await {
+                            Ok(socket) => session.socket = Some(socket),
+                            Err(e) => self.back_off(&mut session, e).await?,
+                        }
+                        continue;
+                    };
+                    match read(socket).await {
+                        Ok(lines) => {
+                            session.failures = 0;
+                            session.pending.extend(lines);
+                        }
+                        Err(e) => {
+                            session.socket = None;
+                            self.back_off(&mut session, e).await?;
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Reacts to a line from the server, returning it when it is not for the client itself.
+    async fn handle(&self, session: &mut Session, line: &str) -> Result<Option<Message>> {
+        let Some(msg) = Message::parse(line) else {
+            return Ok(None);
+        };
+        match msg.command.as_str() {
+            "PING" => {
+                let token = msg.params.first().map_or("tmi.twitch.tv", String::as_str);
+                if let Some(socket) = session.socket.as_mut() {
+                    send(socket, &format!("PONG :{token}")).await?;
+                }
+                return Ok(None);
+            }
+            "RECONNECT" => {
+                debug!("the server asked to reconnect");
+                session.socket = None;
+                session.pending.clear();
+                return Ok(None);
+            }
+            "NOTICE" if msg.params.last().is_some_and(|text| is_auth_failure(text)) => {
+                return Err(Error::Unauthorized(String::from(
+                    "Twitch chat did not accept your token",
+                )));
+            }
+            _ => {}
+        }
+        Ok(Some(msg))
+    }
+
+    /// Sends `message` to `channel` as the active profile, which needs the `chat:edit` scope,
+    /// as a reply when `reply_to` holds a message ID. A message Twitch rejects is reported
+    /// as [`Error::Dropped`].
+    pub async fn say(&self, channel: &str, message: &str, reply_to: Option<&str>) -> Result<()> {
+        let Some(sender) = &self.sender else {
+            return Err(Error::Forbidden(String::from(
+                "Sending to chat over IRC needs a token with the chat:edit scope",
+            )));
+        };
+        // a line break would end the PRIVMSG and start another command
+        if [channel, message].iter().any(|s| s.contains(['\r', '\n'])) {
+            return Err(Error::Invalid(String::from(
+                "Chat messages cannot contain line breaks",
+            )));
+        }
+        let channel = format!("#{}", channel.trim_start_matches('#').to_lowercase());
+        let mut socket = self.connect(&channel, Some(sender)).await?;
+        let mut pending = VecDeque::new();
+        // Twitch confirms the JOIN with the channel's ROOMSTATE, and a message with the
+        // sender's USERSTATE, while a NOTICE says why either did not happen
+        let confirmed = |command: &'static str| {
+            move |msg: &Message| msg.command == command || msg.command == "NOTICE"
+        };
+        let joined = expect(&mut socket, &mut pending, confirmed("ROOMSTATE")).await?;
+        if joined.command == "ROOMSTATE" {
+            let tags = reply_to.map_or_else(String::new, |id| {
+                format!("@reply-parent-msg-id={} ", message::escape(id))
+            });
+            send(&mut socket, &format!("{tags}PRIVMSG {channel} :{message}")).await?;
+        }
+        let answer = match joined.command.as_str() {
+            "ROOMSTATE" => expect(&mut socket, &mut pending, confirmed("USERSTATE")).await?,
+            _ => joined,
+        };
+        let _ = socket.close(None).await;
+        if answer.command == "NOTICE" {
+            let text = answer.params.last().map_or("", String::as_str);
+            return Err(Error::Dropped(match answer.tag("msg-id") {
+                Some(code) => format!("{text} ({code})"),
+                None => text.to_string(),
+            }));
+        }
+        Ok(())
+    }
+
+    /// Connects as `credentials`, or anonymously, and joins `channels`, given as `#a,#b`.
+    async fn connect(
+        &self,
+        channels: &str,
+        credentials: Option<&(String, String)>,
+    ) -> Result<Socket> {
+        debug!(url = %self.url, channels, "connecting");
+        let (mut socket, _) = tokio_tungstenite::connect_async(self.url.as_str())
+            .await
+            .map_err(ws_error)?;
+        send(&mut socket, "CAP REQ :twitch.tv/tags twitch.tv/commands").await?;
+        match credentials {
+            Some((login, token)) => {
+                send(&mut socket, &format!("PASS oauth:{token}")).await?;
+                send(&mut socket, &format!("NICK {login}")).await?;
+            }
+            None => send(&mut socket, &format!("NICK {}", anonymous_nick())).await?,
+        }
+        send(&mut socket, &format!("JOIN {channels}")).await?;
+        Ok(socket)
+    }
+
+    /// Waits before the next connection attempt, or gives up with `err` after too many.
+    async fn back_off(&self, session: &mut Session, err: Error) -> Result<()> {
+        if !matches!(err, Error::WebSocket { .. }) || session.failures >= self.retries {
+            return Err(err);
+        }
+        let delay = BACKOFF
+            .saturating_mul(2u32.saturating_pow(session.failures))
+            .min(MAX_BACKOFF);
+        warn!(
+            "Lost the connection to {SERVICE} ({err}), reconnecting in {}s",
+            delay.as_secs()
+        );
+        session.failures += 1;
+        tokio::time::sleep(delay).await;
+        Ok(())
+    }
+}
+
+/// Reads until a message `wanted` accepts arrives, answering pings on the way.
+async fn expect(
+    socket: &mut Socket,
+    pending: &mut VecDeque<String>,
+    wanted: impl Fn(&Message) -> bool,
+) -> Result<Message> {
+    let waiting = async {
+        loop {
+            let Some(line) = pending.pop_front() else {
+                pending.extend(read(socket).await?);
+                continue;
+            };
+            let Some(msg) = Message::parse(&line) else {
+                continue;
+            };
+            match msg.command.as_str() {
+                "PING" => {
+                    let token = msg.params.first().map_or("tmi.twitch.tv", String::as_str);
+                    send(socket, &format!("PONG :{token}")).await?;
+                }
+                "NOTICE" if msg.params.last().is_some_and(|text| is_auth_failure(text)) => {
+                    return Err(Error::Unauthorized(String::from(
+                        "Twitch chat did not accept your token",
+                    )));
+                }
+                _ if wanted(&msg) => return Ok(msg),
+                _ => {}
+            }
+        }
+    };
+    tokio::time::timeout(CONFIRM_TIMEOUT, waiting)
+        .await
+        .map_err(|_| ws_error(tungstenite::Error::Io(std::io::ErrorKind::TimedOut.into())))?
+}
+
+/// The lines of the next text frame, a closed or silent connection is an error.
+async fn read(socket: &mut Socket) -> Result<Vec<String>> {
+    loop {
+        let frame = tokio::time::timeout(IDLE_TIMEOUT, socket.next())
+            .await
+            .map_err(|_| ws_error(tungstenite::Error::Io(std::io::ErrorKind::TimedOut.into())))?;
+        match frame {
+            Some(Ok(tungstenite::Message::Text(text))) => {
+                return Ok(text.lines().map(String::from).collect());
+            }
+            Some(Ok(tungstenite::Message::Close(_))) | None => {
+                return Err(ws_error(tungstenite::Error::ConnectionClosed));
+            }
+            Some(Ok(_)) => {}
+            Some(Err(e)) => return Err(ws_error(e)),
+        }
+    }
+}
+
+async fn send(socket: &mut Socket, line: &str) -> Result<()> {
+    socket
+        .send(tungstenite::Message::Text(line.to_string()))
+        .await
+        .map_err(ws_error)
+}
+
+fn ws_error(source: tungstenite::Error) -> Error {
+    Error::WebSocket {
+        service: SERVICE,
+        source: Box::new(source),
+    }
+}
+
+fn is_auth_failure(text: &str) -> bool {
+    text.contains("Login authentication failed") || text.contains("Improperly formatted auth")
+}
+
+/// Twitch lets anyone read chat as `justinfan` followed by a number.
+fn anonymous_nick() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    format!("justinfan{}", 10_000 + nanos % 90_000)
+}
@@ -1,27 +1,58 @@
+pub mod modvip;
 pub mod user;
 
-use anyhow::anyhow;
-use anyhow::Result;
-use reqwest::{get, StatusCode};
+use crate::cache::Ttl;
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::http::Http;
+use modvip::ModVip;
 use user::User;
 
-pub struct Api;
+/// Client for the ivr.fi user API and the logs instance next to it.
+pub struct Api {
+    ivr: Http,
+    logs: Http,
+    ivr_url: String,
+    logs_url: String,
+}
 
 impl Api {
-    pub async fn user(login: &str) -> Result<User> {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            ivr: Http::new(config, "ivr"),
+            logs: Http::new(config, "the logs"),
+            ivr_url: config.endpoints.ivr(),
+            logs_url: config.endpoints.logs(),
+        }
+    }
+
+    /// Looks up a user by login name.
+    pub async fn user(&self, login: &str) -> Result<User> {
         if login.len() < 3 || login.len() > 25 {
-            return Err(anyhow!("Invalid username"));
+            return Err(Error::Invalid(format!("{login} is not a valid username")));
         }
-        let url = format!("https://api.ivr.fi/v2/twitch/user/{login}");
-        if let Ok(user) = get(url).await?.json().await {
-            Ok(user)
-        } else {
-            Err(anyhow!("That user could not be found"))
+        let url = format!("{}/twitch/user/{login}", self.ivr_url);
+        self.ivr.get(&url, login, Ttl::User).await?.json()
+    }
+
+    /// The moderators and VIPs of `channel`.
+    pub async fn modvip(&self, channel: &str) -> Result<ModVip> {
+        let url = format!("{}/twitch/modvip/{channel}", self.ivr_url);
+        self.ivr.get(&url, channel, Ttl::Content).await?.json()
+    }
+
+    /// Whether there are logs of `user` in `channel`.
+    pub async fn is_valid_logs_query(&self, user: &str, channel: &str) -> Result<bool> {
+        let url = format!("{}/list?channel={channel}&user={user}", self.logs_url);
+        match self.logs.get(&url, user, Ttl::Content).await {
+            Ok(_) => Ok(true),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
         }
     }
-    pub async fn is_valid_logs_query(user: &str, channel: &str) -> bool {
-        let url = format!("https://logs.ivr.fi/list?channel={channel}&user={user}");
-        let status = get(&url).await.unwrap().status();
-        return status != StatusCode::NOT_FOUND;
+
+    /// Link to the logs of `user` in `channel` on the logs website.
+    pub fn logs_url(&self, user: &str, channel: &str) -> String {
+        format!("{}/?channel={channel}&username={user}", self.logs_url)
     }
 }
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// The moderators and VIPs of a channel.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ModVip {
+    pub mods: Vec<RoleHolder>,
+    pub vips: Vec<RoleHolder>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct RoleHolder {
+    pub id: String,
+    pub login: String,
+    pub display_name: String,
+}
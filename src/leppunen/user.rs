@@ -1,23 +1,17 @@
+#[cfg(feature = "cli")]
+use crate::color;
 use crate::deser::*;
 use crate::format;
-use anyhow::Result;
+use crate::ser::*;
 use chrono::{DateTime, Duration, Utc};
+#[cfg(feature = "cli")]
 use colored::Colorize;
-use serde::Deserialize;
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
 
-pub trait CompactUser {
-    fn print(&self) -> Result<()>;
-}
-
-pub trait VerboseUser {
-    fn print(&self) -> Result<()>;
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all(deserialize = "camelCase"))]
 pub struct User {
-    #[serde(rename(deserialize = "id"))]
+    #[serde(rename = "id")]
     pub uid: String,
     pub banned: bool,
     pub display_name: String,
@@ -27,6 +21,7 @@ pub struct User {
     pub followers: u32,
     #[serde(rename(deserialize = "profileViewCount"))]
     pub channel_views: u32,
+    #[serde(deserialize_with = "deserialize_null_default")]
     pub chat_color: String,
     pub logo: String,
     pub verified_bot: bool,
@@ -39,27 +34,36 @@ pub struct User {
     pub last_broadcast: LastBroadcast,
 }
 
-#[derive(Deserialize, Debug, Clone)]
-#[serde(rename_all = "camelCase")]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all(deserialize = "camelCase"))]
 pub struct Roles {
     pub is_affiliate: bool,
     pub is_partner: bool,
     pub is_staff: Option<bool>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Badge {
     pub title: String,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all(deserialize = "camelCase"))]
 pub struct ChatSettings {
-    #[serde(deserialize_with = "deserialize_millis")]
+    #[serde(
+        deserialize_with = "deserialize_millis",
+        serialize_with = "serialize_millis"
+    )]
     pub chat_delay_ms: Duration,
-    #[serde(deserialize_with = "deserialize_minutes")]
+    #[serde(
+        deserialize_with = "deserialize_minutes",
+        serialize_with = "serialize_minutes"
+    )]
     pub followers_only_duration_minutes: Duration,
-    #[serde(deserialize_with = "deserialize_seconds")]
+    #[serde(
+        deserialize_with = "deserialize_seconds",
+        serialize_with = "serialize_seconds"
+    )]
     pub slow_mode_duration_seconds: Duration,
     #[serde(rename(deserialize = "isEmoteOnlyModeEnabled"))]
     pub emote_only: bool,
@@ -72,8 +76,8 @@ pub struct ChatSettings {
     pub chat_rules: Vec<String>,
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all(deserialize = "camelCase"))]
 pub struct LastBroadcast {
     #[serde(deserialize_with = "deserialize_date_time")]
     pub started_at: DateTime<Utc>,
@@ -84,14 +88,19 @@ impl User {
     pub fn uid(&self) -> String {
         self.uid.clone()
     }
+}
+
+/// The fields as `tl` shows them in a terminal.
+#[cfg(feature = "cli")]
+impl User {
     pub fn display_name_colored(&self) -> String {
-        let (r, g, b) = format::hex_to_rgb(&self.chat_color);
-        self.display_name.truecolor(r, g, b).to_string()
+        match format::hex_to_rgb(&self.chat_color) {
+            Some(rgb) => color::rgb(&self.display_name, rgb),
+            None => self.display_name.clone(),
+        }
     }
     pub fn following(&self) -> String {
-        format::readable_number(self.follows as u32)
-            .magenta()
-            .to_string()
+        format::readable_number(self.follows).magenta().to_string()
     }
     pub fn followers(&self) -> String {
         format::readable_number(self.followers)
@@ -104,112 +113,11 @@ impl User {
             .to_string()
     }
     pub fn chat_color(&self) -> String {
-        let (r, g, b) = format::hex_to_rgb(&self.chat_color);
-        self.chat_color.truecolor(r, g, b).to_string()
-    }
-}
-
-impl CompactUser for User {
-    fn print(&self) -> Result<()> {
-        let follows = format::readable_number(self.follows.into());
-        let followers = format::readable_number(self.followers);
-        let channel_views = format::readable_number(self.channel_views);
-
-        println!(
-            "{}{}",
-            self.display_name_colored().bold(),
-            "'s profile information:".bold()
-        );
-        println!("{} {}", "- User ID:".bold(), self.uid.bold().magenta());
-        println!("{} {}", "- Banned:".bold(), yes_no(self.banned));
-        println!(
-            "{} {}",
-            "- Display name:".bold(),
-            self.display_name_colored().bold()
-        );
-        println!("{} {}", "- Follows:".bold(), follows.bold().magenta());
-        println!("{} {}", "- Following:".bold(), followers.bold().magenta());
-        println!(
-            "{} {}",
-            "- Channel view:".bold(),
-            channel_views.bold().magenta()
-        );
-        println!("{} {}", "- Chat color:".bold(), self.chat_color().bold());
-        println!("{} {}", "- Profile image:".bold(), self.logo.bold().blue());
-        println!(
-            "{} {}",
-            "- Account created:".bold(),
-            self.created_at
-                .date()
-                .naive_utc()
-                .to_string()
-                .bold()
-                .green()
-        );
-        println!(
-            "{} {}",
-            "- Downtime:".bold(),
-            self.last_broadcast.time_since().bold().green()
-        );
-        Ok(())
-    }
-}
-
-impl VerboseUser for User {
-    fn print(&self) -> Result<()> {
-        let follows = format::readable_number(self.follows.into());
-        let followers = format::readable_number(self.followers);
-        let channel_views = format::readable_number(self.channel_views);
-
-        println!(
-            "{}{}",
-            self.display_name_colored().bold(),
-            "'s profile information:".bold()
-        );
-        println!("{} {}", "- User ID:".bold(), self.uid.bold().magenta());
-        println!("{} {}", "- Banned:".bold(), yes_no(self.banned));
-        println!(
-            "{} {}",
-            "- Bio:".bold(),
-            self.bio.clone().unwrap_or_default().bold()
-        );
-        println!("{} {}", "- Follows:".bold(), follows.bold().magenta());
-        println!("{} {}", "- Followers:".bold(), followers.bold().magenta());
-        println!(
-            "{} {}",
-            "- Channel views:".bold(),
-            channel_views.bold().magenta()
-        );
-        println!("{} {}", "- Chat color:".bold(), self.chat_color().bold());
-        println!(
-            "{} {}",
-            "- Profile picture:".bold(),
-            self.logo.bold().blue()
-        );
-        println!("{} {}", "- Verified bot:".bold(), yes_no(self.verified_bot));
-        println!(
-            "{} {}",
-            "- Created at:".bold(),
-            self.created_at
-                .date()
-                .naive_utc()
-                .to_string()
-                .bold()
-                .green()
-        );
-        println!("{} {}", "- Emote prefix:".bold(), self.emote_prefix.bold());
-        println!("{}\n{}", "- Roles:".bold(), self.roles);
-        println!("{}", "- Badges:".bold());
-        for badge in self.badges.iter() {
-            println!("{badge}");
+        match format::hex_to_rgb(&self.chat_color) {
+            Some(rgb) => color::rgb(&self.chat_color, rgb),
+            None if self.chat_color.is_empty() => String::from("none"),
+            None => self.chat_color.clone(),
         }
-        print!("{}\n{}", "- Chat Settings:".bold(), self.chat_settings);
-        print!(
-            "{} {}",
-            "- Downtime:".bold(),
-            self.last_broadcast.time_since().bold().green()
-        );
-        Ok(())
     }
 }
 
@@ -229,75 +137,20 @@ impl Roles {
     }
 }
 
-impl Display for Roles {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        println!("  {} {}", "- Affiliate:".bold(), yes_no(self.is_affiliate));
-        println!("  {} {}", "- Partner:".bold(), yes_no(self.is_partner));
-        write!(
-            f,
-            "{} {}",
-            "  - Staff:".bold(),
-            yes_no(self.is_staff.unwrap_or_default())
-        )
-    }
-}
-
+#[cfg(feature = "cli")]
 impl Badge {
     pub fn color(&self) -> String {
         match self.title.as_ref() {
-            "Verified" => self.title.truecolor(196, 77, 255).to_string(),
-            "Prime Gaming" => self.title.truecolor(38, 139, 255).to_string(),
+            "Verified" => color::rgb(&self.title, (196, 77, 255)),
+            "Prime Gaming" => color::rgb(&self.title, (38, 139, 255)),
             "GLHF Pledge" => self.title.white().to_string(),
-            "GlitchCon 2020" => self.title.truecolor(242, 179, 255).to_string(),
-            "TwitchCon 2020 - Amsterdam" => self.title.truecolor(170, 0, 204).to_string(),
+            "GlitchCon 2020" => color::rgb(&self.title, (242, 179, 255)),
+            "TwitchCon 2020 - Amsterdam" => color::rgb(&self.title, (170, 0, 204)),
             _ => self.title.white().to_string(),
         }
     }
 }
 
-impl Display for Badge {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "  {} {}", "-".bold(), self.color().bold())
-    }
-}
-
-impl Display for ChatSettings {
-    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        println!(
-            "  {} {}",
-            "- Chat delay:".bold(),
-            format::duration_to_hms(self.chat_delay_ms).bold().green()
-        );
-        println!(
-            "  {} {}",
-            "- Followers only duration:".bold(),
-            format::duration_to_hms(self.followers_only_duration_minutes)
-                .bold()
-                .green()
-        );
-        println!(
-            "  {} {}",
-            "- Slow mode duration:".bold(),
-            format::duration_to_hms(self.slow_mode_duration_seconds)
-                .bold()
-                .green()
-        );
-        println!("  {} {}", "- Emote only:".bold(), yes_no(self.emote_only));
-        println!("  {} {}", "- Sub only:".bold(), yes_no(self.sub_only));
-        println!("  {} {}", "- Unique chat:".bold(), yes_no(self.unique_chat));
-        println!(
-            "  {} {}",
-            "- Require verified account:".bold(),
-            yes_no(self.require_verified_account)
-        );
-        println!("  {}", "- Chat rules:".bold());
-        for rule in self.chat_rules.iter() {
-            println!("    {} {}", "-".bold(), rule.bold().yellow());
-        }
-        Ok(())
-    }
-}
-
 impl LastBroadcast {
     pub fn time_since(&self) -> String {
         let now = Utc::now();
@@ -305,11 +158,3 @@ impl LastBroadcast {
         format::duration_to_hms(d)
     }
 }
-
-fn yes_no(b: bool) -> String {
-    if b {
-        "yes".bold().green().to_string()
-    } else {
-        "no".bold().red().to_string()
-    }
-}
@@ -0,0 +1,66 @@
+//! Clients and models behind the `tl` command line tool, for looking up Twitch users,
+//! channels, chats and VODs from other programs.
+//!
+//! Every client is built from a [`Config`], which holds the logged in account, the API
+//! endpoints and the cache settings, and returns data instead of printing it:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use twitch_lookup::{leppunen, Config};
+//!
+//! let config = Config::read(None)?;
+//! let user = leppunen::Api::new(&config).user("forsen").await?;
+//! println!("{} has {} followers", user.display_name, user.followers);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! - [`leppunen::Api`] looks up users ([`leppunen::user::User`]) and logs on ivr.fi.
+//! - [`helix::HelixClient`] lists VODs, followed streams and who is in a channel's chat
+//!   ([`helix::chat::Chat`]) from the Twitch Helix API.
+//! - [`irc::IrcClient`] follows chats live over Twitch IRC, and [`irc::archive::Archive`]
+//!   writes them to daily logs.
+//! - [`decapi::Api`] answers follow ages, titles and live status from DecAPI.
+//! - [`auth`] logs in to Twitch and keeps the token valid.
+//!
+//! API failures are reported as [`error::Error`].
+//!
+//! Each backend is behind a cargo feature of the same name (`ivr`, `decapi`, `helix`), as are
+//! chatters (`chat`), all enabled by default along with `browser` (opening links),
+//! `interactive-config` (prompts) and `keyring` (the OS keyring as the secret store). The
+//! terminal output of the `tl` binary ([`color`], [`render`], [`output`] and [`template`]) is
+//! behind `cli`, which libraries can leave out with `default-features = false`.
+
+pub mod auth;
+pub mod browser;
+pub mod cache;
+#[cfg(feature = "cli")]
+pub mod color;
+pub mod config;
+#[cfg(feature = "decapi")]
+pub mod decapi;
+#[cfg(any(feature = "ivr", feature = "helix"))]
+mod deser;
+pub mod error;
+pub mod format;
+#[cfg(feature = "helix")]
+pub mod helix;
+pub mod http;
+#[cfg(feature = "chat")]
+pub mod irc;
+#[cfg(feature = "ivr")]
+pub mod leppunen;
+#[cfg(feature = "cli")]
+pub mod output;
+pub mod recording;
+#[cfg(feature = "cli")]
+pub mod render;
+pub mod secrets;
+#[cfg(feature = "ivr")]
+mod ser;
+mod stub;
+#[cfg(feature = "cli")]
+pub mod template;
+
+pub use config::Config;
+pub use error::{Error, Result};
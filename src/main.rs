@@ -1,110 +1,519 @@
 mod cli;
-mod config;
-mod decapi;
-mod deser;
-mod format;
-mod helix;
-mod leppunen;
-mod tmi;
 
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Parser;
-use cli::{Action, Args, ChatAction, UserAction};
+#[cfg(feature = "ivr")]
+use cli::UserAction;
+use cli::{Action, Args, AuthAction, CacheAction};
+#[cfg(feature = "chat")]
+use cli::{ChatAction, TailFilters};
 use colored::Colorize;
-use config::Config;
-use helix::HelixClient;
-use leppunen::user::{CompactUser, VerboseUser};
-use tmi::Chat;
+#[cfg(feature = "chat")]
+use futures::future;
+#[cfg(feature = "helix")]
+use futures::{StreamExt, TryStreamExt};
+use serde_json::json;
+use std::io::{self, IsTerminal, Write};
+#[cfg(feature = "helix")]
+use std::pin::pin;
+use std::process::ExitCode;
+use std::time::Instant;
+use tracing::{debug, info_span, Instrument};
+use tracing_subscriber::EnvFilter;
+use twitch_lookup::cache::{self, Cache};
+use twitch_lookup::config::{CacheMode, Config};
+#[cfg(feature = "decapi")]
+use twitch_lookup::decapi;
+use twitch_lookup::error::Error;
+#[cfg(feature = "chat")]
+use twitch_lookup::helix::chat::Chat;
+#[cfg(feature = "helix")]
+use twitch_lookup::helix::HelixClient;
+#[cfg(feature = "helix")]
+use twitch_lookup::helix::{channel::Channel, vod::Vod};
+#[cfg(feature = "chat")]
+use twitch_lookup::irc::{
+    archive::{self, Archive},
+    filter::ChatFilter,
+    limits::{self, SentLog},
+    ChatMessage, IrcClient,
+};
+#[cfg(feature = "ivr")]
+use twitch_lookup::leppunen;
+#[cfg(feature = "ivr")]
+use twitch_lookup::leppunen::user::User;
+use twitch_lookup::recording::Recording;
+#[cfg(any(feature = "ivr", feature = "helix"))]
+use twitch_lookup::render::Render;
+#[cfg(feature = "ivr")]
+use twitch_lookup::render::{CompactUser, VerboseUser};
+#[cfg(any(feature = "ivr", feature = "helix"))]
+use twitch_lookup::template::Fields;
+use twitch_lookup::template::Template;
+use twitch_lookup::{auth, browser, color, format, http, output};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let config = Config::read()?;
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            report(&e);
+            ExitCode::from(e.downcast_ref::<Error>().map_or(1, Error::exit_code))
+        }
+    }
+}
+
+/// Prints an error to stderr, with a hint on what to do about it when there is one.
+fn report(err: &anyhow::Error) {
+    eprintln!("{} {}", "Error:".bold().red(), err.to_string().bold());
+    if let Some(cause) = err.chain().skip(1).last() {
+        eprintln!("  {} {cause}", "caused by:".dimmed());
+    }
+    if let Some(hint) = err.downcast_ref::<Error>().and_then(Error::hint) {
+        eprintln!("{}", hint.yellow());
+    }
+}
+
+async fn run() -> Result<()> {
     let args = Args::parse();
+    color::init(args.color);
+    init_tracing(args.verbose);
+    let mut config = Config::read(args.profile.as_deref())?;
+    // a template that cannot work with the command fails before anything is fetched
+    let template = match &args.format {
+        Some(format) => {
+            let template = Template::parse(config.templates.get(format).unwrap_or(format))?;
+            template.check(template_fields(&args.action)?)?;
+            Some(template)
+        }
+        None => None,
+    };
+    if args.no_cache {
+        config.cache.mode = CacheMode::Off;
+    } else if args.refresh {
+        config.cache.mode = CacheMode::Refresh;
+    } else if args.offline {
+        if !args.action.works_offline() {
+            bail!(Error::Invalid(String::from(
+                "--offline only works with the user, title, ll and vods commands"
+            )));
+        }
+        config.cache.mode = CacheMode::Offline;
+    }
+    if let Some(dir) = &args.record {
+        // every response has to come from the services to end up in the recording
+        config.cache.mode = CacheMode::Refresh;
+        config.http.recording = Recording::Record(dir.clone());
+    } else if let Some(dir) = &args.replay {
+        if !args.action.calls_apis() {
+            bail!(Error::Invalid(String::from(
+                "--replay only works with commands that look something up"
+            )));
+        }
+        if !dir.is_dir() {
+            bail!(Error::Invalid(format!(
+                "{} is not a directory",
+                dir.display()
+            )));
+        }
+        config.cache.mode = CacheMode::Off;
+        config.http.recording = Recording::Replay(dir.clone());
+    }
+    if args.dry_run {
+        if !args.action.calls_apis() {
+            bail!(Error::Invalid(String::from(
+                "--dry-run only works with commands that look something up"
+            )));
+        }
+        config.http.dry_run = true;
+    }
+    // offline, replaying or dry running there is no token to check, nothing goes out with it
+    let online = !args.offline && args.replay.is_none() && !args.dry_run;
+    if args.action.needs_secrets() && online {
+        config.unlock()?;
+    }
+    if args.action.needs_login() && online {
+        auth::ensure_valid(&mut config, &args.action.required_scopes(), &show_notice).await?;
+    }
+    let offline = args.offline;
+    let dry_run = args.dry_run;
+    let output = args.output;
+    let stdout = io::stdout();
+    let mut printed = Vec::new();
+    // a dry run prints the requests it would send instead of what it made of the answers, and
+    // offline the answers are held back to be marked with their age
+    let out: Box<dyn Write + '_> = if dry_run {
+        Box::new(io::sink())
+    } else if offline {
+        Box::new(&mut printed)
+    } else {
+        Box::new(stdout.lock())
+    };
+    let span = info_span!("command", action = ?args.action);
+    let started = Instant::now();
+    let mut result = dispatch(args, config, template, out)
+        .instrument(span.clone())
+        .await;
+    if dry_run {
+        let dry_run = http::dry_run();
+        for request in &dry_run.requests {
+            println!("{request}");
+        }
+        // failures past a made-up answer are about the made-up answer
+        if dry_run.stubbed {
+            result = Ok(());
+        }
+    }
+    span.in_scope(|| debug!(elapsed_ms = started.elapsed().as_millis() as u64, "done"));
+    if offline {
+        if let Some(cached_at) = cache::oldest_served() {
+            printed = output::mark_cached(&printed, output, cached_at)?;
+        }
+        stdout.lock().write_all(&printed)?;
+    }
+    result
+}
+
+/// A chat client reading as the active profile when its token may read chat, which is
+/// checked first, and anonymously otherwise.
+#[cfg(feature = "chat")]
+async fn irc_client(config: &mut Config) -> Result<IrcClient> {
+    if config.profile().scopes.iter().any(|s| s == "chat:read") {
+        config.unlock()?;
+        auth::ensure_valid(config, &["chat:read"], &show_notice).await?;
+    }
+    Ok(IrcClient::new(config))
+}
+
+/// Follows `channel`'s chat and hands the messages let through by `filters` and the `[chat]`
+/// rules to `show`, until `count` were shown. Highlighted ones also ring the bell and run the
+/// hook when asked to.
+#[cfg(feature = "chat")]
+async fn tail_chat(
+    config: &mut Config,
+    channel: &str,
+    count: Option<usize>,
+    filters: &TailFilters,
+    mut show: impl FnMut(&ChatMessage) -> Result<()>,
+) -> Result<()> {
+    let login = config.profile().login.clone();
+    let mut filter = ChatFilter::new(&config.chat)?;
+    filter.users = filters.users.clone();
+    if let Some(pattern) = &filters.grep {
+        filter.grep(pattern)?;
+    }
+    filter.exclude_bots = filters.exclude_bots;
+    filter.mods_only = filters.mods_only;
+    if filters.mentions {
+        if login.is_empty() {
+            bail!(Error::Invalid(String::from(
+                "--mentions needs an account to look for, log in with `tl auth login`"
+            )));
+        }
+        filter.mentions = Some(login.clone());
+    }
+    filter.login = (!login.is_empty()).then_some(login);
+    for word in &filters.highlights {
+        filter.highlight(word);
+    }
+    let bell = filters.bell || config.chat.bell;
+
+    let client = irc_client(config).await?;
+    let mut messages = pin!(client
+        .tail(channel)
+        .try_filter(|message| future::ready(filter.accepts(message)))
+        .take(count.unwrap_or(usize::MAX)));
+    while let Some(mut message) = messages.try_next().await? {
+        message.highlighted = filter.highlights(&message);
+        show(&message)?;
+        if !message.highlighted {
+            continue;
+        }
+        if bell {
+            eprint!("\x07");
+        }
+        if !config.chat.hook.is_empty() {
+            if let Err(e) = message.run_hook(&config.chat.hook) {
+                eprintln!("Could not run the chat hook: {e}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends `message` to `channel`, a lowercase login, through Helix or over IRC when the token
+/// may only chat there, once it passes Twitch's rate limit and duplicate rules. Returns the ID
+/// Helix gave the message.
+#[cfg(feature = "chat")]
+async fn say(
+    config: &Config,
+    channel: &str,
+    message: &str,
+    reply_to: Option<&str>,
+) -> Result<Option<String>> {
+    let profile = config.profile();
+    let has_scope = |scope: &str| profile.scopes.iter().any(|s| s == scope);
+    let via_helix = has_scope("user:write:chat");
+    if !via_helix && !has_scope("chat:edit") {
+        bail!(Error::Forbidden(String::from(
+            "Sending chat messages needs the user:write:chat scope, add it to scopes in [auth] \
+             and run `tl auth login`"
+        )));
+    }
+    // refused before anything is looked up for it
+    limits::validate(message)?;
+    let mut log = SentLog::load()?;
+    if let Err(e) = log.check(&profile.login, channel, message, false) {
+        // the broadcaster, moderators and VIPs have looser limits, which only matter now
+        let roles = leppunen::Api::new(config).modvip(channel).await?;
+        let privileged = profile.login == channel
+            || roles
+                .mods
+                .iter()
+                .chain(&roles.vips)
+                .any(|holder| holder.login == profile.login);
+        if !privileged {
+            return Err(e);
+        }
+        log.check(&profile.login, channel, message, true)?;
+    }
+
+    let message_id = if via_helix {
+        let sent = HelixClient::new(config)?
+            .send_chat_message(channel, message, reply_to)
+            .await?;
+        Some(sent.message_id)
+    } else {
+        if config.http.dry_run {
+            http::would_send(format!("PRIVMSG #{channel} :{message}"));
+            return Ok(None);
+        }
+        if matches!(config.http.recording, Recording::Replay(_)) {
+            bail!(Error::Invalid(String::from(
+                "Messages sent over IRC can not be replayed"
+            )));
+        }
+        IrcClient::new(config)
+            .say(channel, message, reply_to)
+            .await?;
+        None
+    };
+    if !config.http.dry_run {
+        log.push(&profile.login, channel, message)?;
+    }
+    Ok(message_id)
+}
+
+/// Confirms a message that `say` sent.
+#[cfg(feature = "chat")]
+fn sent(
+    out: &mut impl Write,
+    output: output::OutputFormat,
+    channel: &str,
+    message_id: Option<String>,
+) -> Result<()> {
+    if !output.is_text() {
+        return output::emit(
+            out,
+            output,
+            &json!({ "channel": channel, "sent": true, "message_id": message_id }),
+        );
+    }
+    writeln!(out, "{} {}", "Sent to".bold(), channel.bold().magenta())?;
+    Ok(())
+}
+
+/// Shows what a login needs the user to do on stderr, opening the browser where it helps.
+fn show_notice(notice: auth::Notice) {
+    match notice {
+        auth::Notice::EnterCode { uri, code } => {
+            eprintln!(
+                "{} {} {} {}",
+                "Open".bold(),
+                uri.bold().blue(),
+                "and enter the code".bold(),
+                code.bold().magenta()
+            );
+            // Not having a browser is fine, the link is printed above.
+            browser::open(uri);
+        }
+        auth::Notice::OpenUrl { url } => {
+            eprintln!("{} {}", "Log in through your browser:".bold(), url.blue());
+            browser::open(url);
+        }
+        auth::Notice::LoggedIn { login } => {
+            eprintln!("{} {}", "Logged in as".bold(), login.bold().green());
+        }
+    }
+}
+
+/// Sends traces to stderr, filtered by `TL_LOG` (e.g. `TL_LOG=twitch_lookup=trace`) when set and
+/// by the number of `-v`s otherwise.
+fn init_tracing(verbose: u8) {
+    let filter = match std::env::var("TL_LOG") {
+        Ok(filter) if !filter.is_empty() => EnvFilter::new(filter),
+        _ => EnvFilter::new(match verbose {
+            0 => "warn",
+            1 => "warn,twitch_lookup=debug,tl=debug",
+            _ => "debug,twitch_lookup=trace,tl=trace",
+        }),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(color::enabled() && io::stderr().is_terminal())
+        .init();
+}
+
+async fn dispatch(
+    args: Args,
+    mut config: Config,
+    template: Option<Template>,
+    mut out: Box<dyn Write + '_>,
+) -> Result<()> {
+    let output = args.output;
+
+    if let Some(template) = &template {
+        return render_template(&mut out, template, args.action, &config).await;
+    }
 
     match args.action {
+        #[cfg(feature = "chat")]
         Action::Chat(chat_action) => match chat_action {
             ChatAction::Streamer { channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
+                if !output.is_text() {
+                    let present = !chat.chatters().broadcaster().is_empty();
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "channel": channel, "present": present }),
+                    );
+                }
                 if chat.chatters().broadcaster().is_empty() {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         &channel.bold().red(),
                         "is not in chat".bold().red()
-                    );
+                    )?;
                     return Ok(());
                 }
-                println!(
+                writeln!(
+                    out,
                     "{} {}",
                     &channel.bold().bright_green(),
                     "is in chat".bold().bright_green()
-                );
+                )?;
             }
             ChatAction::Mods { channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
                 let mods = chat.chatters().moderators();
-                println!(
+                if !output.is_text() {
+                    return output::emit_list(&mut out, output, mods);
+                }
+                writeln!(
+                    out,
                     "{} {}{}",
                     "Moderators in".bold(),
                     &channel.bold(),
                     ":".bold()
-                );
+                )?;
                 for (i, moderator) in mods.iter().enumerate() {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         (i + 1).to_string().bold().magenta(),
                         moderator.bold()
-                    );
+                    )?;
                 }
             }
             ChatAction::Vips { channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
                 let vips = chat.chatters().vips();
-                println!("{} {}{}", "VIPs in".bold(), &channel.bold(), ":".bold());
+                if !output.is_text() {
+                    return output::emit_list(&mut out, output, vips);
+                }
+                writeln!(
+                    out,
+                    "{} {}{}",
+                    "VIPs in".bold(),
+                    &channel.bold(),
+                    ":".bold()
+                )?;
                 for (i, vip) in vips.iter().enumerate() {
-                    println!("{} {}", (i + 1).to_string().bold().magenta(), &vip.bold());
+                    writeln!(
+                        out,
+                        "{} {}",
+                        (i + 1).to_string().bold().magenta(),
+                        &vip.bold()
+                    )?;
                 }
             }
             ChatAction::Normals { channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
                 let normals = chat.chatters().viewers();
-                println!(
+                if !output.is_text() {
+                    return output::emit_list(&mut out, output, normals);
+                }
+                writeln!(
+                    out,
                     "{} {}{}",
                     "Normal chatters in".bold(),
                     &channel.bold(),
                     ":".bold()
-                );
+                )?;
                 for (i, normal) in normals.iter().enumerate() {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         (i + 1).to_string().bold().magenta(),
                         &normal.bold()
-                    );
+                    )?;
                 }
             }
             ChatAction::Count { channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
                 let count = chat.chatter_count();
-                println!(
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "channel": channel, "chatter_count": count }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{} {} {} {}",
                     "There are currently".bold(),
                     count.to_string().bold().magenta(),
                     "chatters in".bold(),
                     &channel.bold()
-                );
+                )?;
             }
             ChatAction::Present { user, channel } => {
-                let chat = Chat::fetch(&channel).await?;
+                let chat = HelixClient::new(&config)?.get_chat(&channel).await?;
+                if !output.is_text() {
+                    let present = chat.chatters().is_present(&user);
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "user": user, "channel": channel, "present": present }),
+                    );
+                }
                 if chat.chatters().is_present(&user) {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {} {}{}",
                         &user.bold(),
                         "is currently present in".bold(),
                         &channel.bold(),
                         "'s chat".bold()
-                    );
+                    )?;
                     return Ok(());
                 }
-                println!(
+                writeln!(
+                    out,
                     "{} {} {} {} {}{}",
                     &user.bold(),
                     "is".bold(),
@@ -112,108 +521,256 @@ async fn main() -> Result<()> {
                     "currently in".bold(),
                     &channel.bold(),
                     "'s chat".bold()
+                )?;
+            }
+            ChatAction::Tail {
+                channel,
+                count,
+                filters,
+            } => {
+                tail_chat(&mut config, &channel, count, &filters, |message| {
+                    if output.is_text() {
+                        message.render(&mut out)?;
+                        return Ok(());
+                    }
+                    output::emit(&mut out, output, message)
+                })
+                .await?;
+            }
+            ChatAction::Say { channel, message } => {
+                let channel = channel.trim_start_matches('#').to_lowercase();
+                let message_id = say(&config, &channel, &message.join(" "), None).await?;
+                sent(&mut out, output, &channel, message_id)?;
+            }
+            ChatAction::Reply {
+                channel,
+                message_id,
+                message,
+            } => {
+                let channel = channel.trim_start_matches('#').to_lowercase();
+                let message_id =
+                    say(&config, &channel, &message.join(" "), Some(&message_id)).await?;
+                sent(&mut out, output, &channel, message_id)?;
+            }
+            ChatAction::Record { channels, dir } => {
+                let dir = match dir {
+                    Some(dir) => dir,
+                    None => config.chat.record_dir()?,
+                };
+                let mut archive = Archive::open(&dir, config.chat.keep_days)?;
+                let client = irc_client(&mut config).await?.keep_reconnecting();
+                let channels: Vec<&str> = channels.iter().map(String::as_str).collect();
+                eprintln!(
+                    "{} {} {} {}",
+                    "Recording".bold(),
+                    channels.join(", ").bold().magenta(),
+                    "to".bold(),
+                    dir.display()
                 );
+                let mut messages = pin!(client.messages(&channels));
+                let next_day =
+                    || tokio::time::Instant::now() + archive::until_next_day(chrono::Utc::now());
+                let mut rotation = next_day();
+                loop {
+                    tokio::select! {
+                        message = messages.try_next() => match message? {
+                            Some(message) => {
+                                archive.write(&message)?;
+                            }
+                            None => break,
+                        },
+                        // quiet channels are rotated when the day changes, not when they chat
+                        _ = tokio::time::sleep_until(rotation) => {
+                            archive.rotate(chrono::Utc::now().naive_utc().date())?;
+                            rotation = next_day();
+                        }
+                    }
+                }
             }
         },
         Action::Command { bot, cmd_name } => {
             if bot == "supi" || bot == "supibot" {
                 let uri = format!("https://supinic.com/bot/command/detail/{cmd_name}");
-                webbrowser::open(&uri)?;
+                let opened = browser::open(&uri);
+                if !output.is_text() {
+                    output::emit(&mut out, output, &json!({ "url": uri }))?;
+                } else if !opened {
+                    writeln!(out, "{}", uri.bold().blue())?;
+                }
             }
             // TODO: add more bots
         }
+        #[cfg(feature = "ivr")]
         Action::User(user_action) => match user_action {
             UserAction::Compact { user } => {
-                let user: Box<dyn CompactUser> = Box::new(leppunen::Api::user(&user).await?);
-                user.print()?;
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(&mut out, output, &user);
+                }
+                CompactUser::render(&user, &mut out)?;
             }
             UserAction::Verbose { user } => {
-                let user: Box<dyn VerboseUser> = Box::new(leppunen::Api::user(&user).await?);
-                user.print()?;
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(&mut out, output, &user);
+                }
+                VerboseUser::render(&user, &mut out)?;
             }
             UserAction::Bc { user } => {
-                let user = leppunen::Api::user(&user).await?;
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "banned": user.banned }),
+                    );
+                }
                 if user.banned {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         user.display_name_colored().bold(),
                         "is banned".bold().red()
-                    );
+                    )?;
                 } else {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         user.display_name_colored().bold(),
                         "is not banned".bold().green()
-                    );
+                    )?;
                 }
             }
             UserAction::Dn { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!("{}", user.display_name_colored().bold());
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "display_name": user.display_name }),
+                    );
+                }
+                writeln!(out, "{}", user.display_name_colored().bold())?;
             }
             UserAction::Uf { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "follows": user.follows }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{} {} {} {}",
                     user.display_name_colored().bold(),
                     "is following".bold(),
                     user.following().bold(),
                     "people".bold()
-                );
+                )?;
             }
             UserAction::Fu { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "followers": user.followers }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{} {} {} {}",
                     user.display_name_colored().bold(),
                     "has".bold(),
                     user.followers().bold(),
                     "followers".bold()
-                );
+                )?;
             }
             UserAction::Cv { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "channel_views": user.channel_views }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{} {} {} {}",
                     user.display_name_colored().bold(),
                     "has".bold(),
                     user.channel_views().bold(),
                     "channel views".bold()
-                );
+                )?;
             }
             UserAction::Cc { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!("{}", user.chat_color().bold());
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "chat_color": user.chat_color }),
+                    );
+                }
+                writeln!(out, "{}", user.chat_color().bold())?;
             }
             UserAction::Pfp { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "logo": user.logo }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{}{} {}",
                     user.display_name_colored().bold(),
                     "'s profile image:".bold(),
                     user.logo.bold().blue()
-                );
+                )?;
             }
             UserAction::Bot { user } => {
-                let user = leppunen::Api::user(&user).await?;
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "verified_bot": user.verified_bot }),
+                    );
+                }
                 if user.verified_bot {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         user.display_name_colored().bold(),
                         "is a verified bot".bold().green()
-                    );
+                    )?;
                 } else {
-                    println!(
+                    writeln!(
+                        out,
                         "{} {}",
                         user.display_name_colored().bold(),
                         "is not a bot".bold().red()
-                    );
+                    )?;
                 }
             }
             UserAction::Cd { user } => {
-                let user = leppunen::Api::user(&user).await?;
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "created_at": user.created_at }),
+                    );
+                }
                 // TODO: time since account creation
-                println!(
+                writeln!(
+                    out,
                     "{} {} {}",
                     user.display_name_colored().bold(),
                     "was created on".bold(),
@@ -223,99 +780,160 @@ async fn main() -> Result<()> {
                         .to_string()
                         .bold()
                         .green()
-                );
+                )?;
             }
             UserAction::Ep { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "emote_prefix": user.emote_prefix }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{}{} {}",
                     user.display_name_colored().bold(),
                     "'s emote prefix:".bold(),
                     user.emote_prefix.bold()
-                );
+                )?;
             }
             UserAction::Roles { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!("{}", "Roles:".bold());
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "roles": user.roles.get_active() }),
+                    );
+                }
+                writeln!(out, "{}", "Roles:".bold())?;
                 for role in user.roles.get_active() {
-                    println!("{} {}", "-".bold(), role.bold().green());
+                    writeln!(out, "{} {}", "-".bold(), role.bold().green())?;
                 }
             }
             UserAction::Badges { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!("{}", "Badges:".bold());
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "badges": user.badges }),
+                    );
+                }
+                writeln!(out, "{}", "Badges:".bold())?;
                 for badge in user.badges.iter() {
-                    println!("{} {}", "-".bold(), badge.color().bold());
+                    writeln!(out, "{} {}", "-".bold(), badge.color().bold())?;
                 }
             }
             UserAction::Cs { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                print!(
-                    "{}{}\n{}",
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "chat_settings": user.chat_settings }),
+                    );
+                }
+                writeln!(
+                    out,
+                    "{}{}",
                     user.display_name_colored().bold(),
-                    "'s chat settings:".bold(),
-                    user.chat_settings
-                );
+                    "'s chat settings:".bold()
+                )?;
+                user.chat_settings.render(&mut out)?;
             }
             UserAction::Dt { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "last_broadcast": user.last_broadcast }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{} {} {}",
                     user.display_name_colored().bold(),
                     "has been offline for".bold(),
                     user.last_broadcast.time_since().bold()
-                );
+                )?;
             }
             UserAction::Id { user } => {
-                let user = leppunen::Api::user(&user).await?;
-                println!(
+                let user = leppunen::Api::new(&config).user(&user).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({ "login": user.login, "id": user.uid }),
+                    );
+                }
+                writeln!(
+                    out,
                     "{}{} {}",
                     user.display_name_colored().bold(),
                     "'s user ID is:".bold(),
                     user.uid().to_string().bold().magenta()
-                );
+                )?;
             }
             UserAction::Link { user } => {
                 let url = format!("https://twitch.tv/{user}");
-                println!("{}", url.bold().blue());
+                if !output.is_text() {
+                    return output::emit(&mut out, output, &json!({ "login": user, "url": url }));
+                }
+                writeln!(out, "{}", url.bold().blue())?;
             }
         },
+        #[cfg(feature = "ivr")]
         Action::Logs { user, channel } => {
-            if let Some(channel) = channel {
-                if leppunen::Api::is_valid_logs_query(&user, &channel).await {
-                    let url = format!("https://logs.ivr.fi/?channel={channel}&username={user}");
-                    webbrowser::open(&url)?;
-                } else {
-                    println!(
-                        "{} {} {}",
-                        "That user or channel could".bold(),
-                        "not".bold().red(),
-                        "be found".bold()
-                    );
-                }
+            let (user, channel) = if let Some(channel) = channel {
+                (user, channel)
             } else {
-                let login = config.login;
-                if leppunen::Api::is_valid_logs_query(&login, &user).await {
-                    let url = format!("https://logs.ivr.fi/?channel={user}&username={login}");
-                    webbrowser::open(&url)?;
-                } else {
-                    println!(
-                        "{} {} {}",
-                        "That user or channel could".bold(),
-                        "not".bold().red(),
-                        "be found".bold()
-                    );
-                }
+                (config.profile().login.clone(), user)
             };
+            let api = leppunen::Api::new(&config);
+            let found = api.is_valid_logs_query(&user, &channel).await?;
+            let url = api.logs_url(&user, &channel);
+            let opened = found && !config.http.dry_run && browser::open(&url);
+            if !output.is_text() {
+                return output::emit(&mut out, output, &json!({ "found": found, "url": url }));
+            }
+            if !found {
+                writeln!(
+                    out,
+                    "{} {} {}",
+                    "That user or channel could".bold(),
+                    "not".bold().red(),
+                    "be found".bold()
+                )?;
+            } else if !opened {
+                writeln!(out, "{}", url.bold().blue())?;
+            }
         }
+        #[cfg(feature = "decapi")]
         Action::Fa { user, channel } => {
             let (user, target) = if let Some(c) = channel {
                 (user, c)
             } else {
-                (config.login.to_string(), user)
+                (config.profile().login.clone(), user)
             };
-            let fa = decapi::follow_age(&user, &target).await?;
-            let output = if fa.contains("does not follow") {
+            let fa = decapi::Api::new(&config).follow_age(&user, &target).await?;
+            let following = !fa.contains("does not follow");
+            if !output.is_text() {
+                return output::emit(
+                    &mut out,
+                    output,
+                    &json!({
+                        "user": user,
+                        "channel": target,
+                        "following": following,
+                        "followage": following.then_some(fa),
+                    }),
+                );
+            }
+            let message = if !following {
                 format!(
                     "{} does {} follow {}",
                     user.blue(),
@@ -330,55 +948,418 @@ async fn main() -> Result<()> {
                     fa.green()
                 )
             };
-            println!("{}", output.bold());
+            writeln!(out, "{}", message.bold())?;
         }
+        #[cfg(feature = "decapi")]
         Action::Title { channel } => {
-            println!(
+            let title = decapi::Api::new(&config).title(&channel).await?;
+            if !output.is_text() {
+                let url = format!("https://twitch.tv/{channel}");
+                return output::emit(
+                    &mut out,
+                    output,
+                    &json!({ "channel": channel, "title": title, "url": url }),
+                );
+            }
+            writeln!(
+                out,
                 "{}\n{}{}",
-                decapi::title(&channel).await?.bold(),
+                title.bold(),
                 "https://twitch.tv/".blue().bold(),
                 channel.blue().bold()
-            );
+            )?;
         }
+        #[cfg(feature = "decapi")]
         Action::Live { channel } => {
-            if let Some(view_count) = decapi::is_live(&channel).await {
-                println!(
+            let view_count = decapi::Api::new(&config).is_live(&channel).await?;
+            if !output.is_text() {
+                let url = format!("https://twitch.tv/{channel}");
+                let viewer_count = view_count.and_then(|v| v.trim().parse::<u32>().ok());
+                return output::emit(
+                    &mut out,
+                    output,
+                    &json!({
+                        "channel": channel,
+                        "live": viewer_count.is_some(),
+                        "viewer_count": viewer_count,
+                        "url": url,
+                    }),
+                );
+            }
+            if let Some(view_count) = view_count {
+                writeln!(
+                    out,
                     "{}\n{}{}",
                     format!("{channel} is live with {} viewer(s)", view_count.magenta()).bold(),
                     "https://twitch.tv/".bold().blue(),
                     channel.bold().blue()
-                )
+                )?;
             } else {
-                println!(
+                writeln!(
+                    out,
                     "{} {}",
                     channel.blue().bold(),
                     "is currently offline".bold()
-                )
+                )?;
             }
         }
+        #[cfg(feature = "helix")]
         Action::Vods { channel, amount } => {
-            let client = HelixClient::new(&config);
-            let vods = client.get_vods(&channel, amount).await;
-            if let Some(vods) = vods {
-                for (i, vod) in vods.iter().enumerate() {
-                    println!("{} {}\n{vod}", "Vod".bold(), (i + 1).to_string().bold());
-                }
+            let client = HelixClient::new(&config)?;
+            let vods = client.get_vods(&channel, amount).await?;
+            if !output.is_text() {
+                let vods: Vec<_> = vods.try_collect().await?;
+                return output::emit_list(&mut out, output, &vods);
+            }
+            let mut vods = pin!(vods.enumerate());
+            while let Some((i, vod)) = vods.next().await {
+                writeln!(out, "{} {}", "Vod".bold(), (i + 1).to_string().bold())?;
+                vod?.render(&mut out)?;
             }
         }
+        #[cfg(feature = "helix")]
         Action::Ll => {
-            let client = HelixClient::new(&config);
-            let mut channels = client
-                .get_live_followed_channels(&config.user_id)
-                .await
-                .unwrap_or_default();
+            let client = HelixClient::new(&config)?;
+            let mut channels: Vec<_> = client
+                .get_live_followed_channels(&config.profile().user_id)
+                .try_collect()
+                .await?;
             channels.reverse();
+            if !output.is_text() {
+                return output::emit_list(&mut out, output, &channels);
+            }
             for channel in channels {
-                println!("{channel}");
+                channel.render(&mut out)?;
             }
         }
         Action::Emotes { user } => {
-            webbrowser::open(&format!("https://emotes.raccatta.cc/twitch/{user}"))?;
+            let url = format!("https://emotes.raccatta.cc/twitch/{user}");
+            let opened = browser::open(&url);
+            if !output.is_text() {
+                output::emit(&mut out, output, &json!({ "url": url }))?;
+            } else if !opened {
+                writeln!(out, "{}", url.bold().blue())?;
+            }
+        }
+        Action::Auth(auth_action) => match auth_action {
+            AuthAction::Login { token } => {
+                if let Some(token) = token {
+                    auth::login_with_token(&mut config, &token).await?;
+                } else {
+                    auth::login(&mut config, &show_notice).await?;
+                }
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({
+                            "profile": config.active_profile,
+                            "login": config.profile().login,
+                            "user_id": config.profile().user_id,
+                        }),
+                    );
+                }
+                writeln!(
+                    out,
+                    "{} {}",
+                    "Logged in as".bold(),
+                    config.profile().login.bold().green()
+                )?;
+            }
+            AuthAction::Status => {
+                let valid = auth::check(&mut config).await?;
+                let profile = config.profile();
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({
+                            "profile": config.active_profile,
+                            "logged_in": profile.is_logged_in(),
+                            "valid": valid,
+                            "login": profile.login,
+                            "user_id": profile.user_id,
+                            "scopes": profile.scopes,
+                            "expires_at": profile.expires_at,
+                        }),
+                    );
+                }
+                if !profile.is_logged_in() {
+                    writeln!(
+                        out,
+                        "{} {}",
+                        "Not logged in on profile".bold().red(),
+                        config.active_profile.bold()
+                    )?;
+                    return Ok(());
+                }
+                writeln!(
+                    out,
+                    "{} {} ({}) {} {}",
+                    "Logged in as".bold(),
+                    profile.login.bold().green(),
+                    profile.user_id.bold().magenta(),
+                    "on profile".bold(),
+                    config.active_profile.bold()
+                )?;
+                writeln!(out, "{} {}", "- Scopes:".bold(), profile.scopes.join(", "))?;
+                let expiry = match profile.expires_at {
+                    Some(at) => format!(
+                        "{} (in {})",
+                        at.format("%Y-%m-%d %H:%M:%S UTC"),
+                        format::duration_to_hms(at.signed_duration_since(chrono::Utc::now()))
+                    ),
+                    None => String::from("never"),
+                };
+                writeln!(out, "{} {}", "- Expires:".bold(), expiry.bold().green())?;
+                let validity = if valid {
+                    "valid".bold().green()
+                } else {
+                    "rejected by Twitch, run `tl auth login`".bold().red()
+                };
+                writeln!(out, "{} {}", "- Token:".bold(), validity)?;
+            }
+            AuthAction::Profiles => {
+                let profiles: Vec<_> = config
+                    .profiles
+                    .iter()
+                    .filter(|(_, p)| p.is_logged_in())
+                    .collect();
+                if !output.is_text() {
+                    let profiles: Vec<_> = profiles
+                        .iter()
+                        .map(|(name, p)| {
+                            json!({
+                                "profile": name,
+                                "login": p.login,
+                                "user_id": p.user_id,
+                                "default": **name == config.default_profile,
+                            })
+                        })
+                        .collect();
+                    return output::emit_list(&mut out, output, &profiles);
+                }
+                for (name, profile) in profiles {
+                    let marker = if *name == config.default_profile {
+                        "*"
+                    } else {
+                        "-"
+                    };
+                    writeln!(
+                        out,
+                        "{} {} ({})",
+                        marker.bold(),
+                        name.bold().green(),
+                        profile.login.bold()
+                    )?;
+                }
+            }
+            AuthAction::Logout => {
+                auth::logout(&mut config).await?;
+                if !output.is_text() {
+                    return output::emit(&mut out, output, &json!({ "logged_in": false }));
+                }
+                writeln!(out, "{}", "Logged out".bold())?;
+            }
+            AuthAction::Switch {
+                profile: Some(profile),
+            } => {
+                config.default_profile = profile.clone();
+                config.active_profile = profile;
+                auth::ensure_login(&mut config, &show_notice).await?;
+                config.save()?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({
+                            "profile": config.active_profile,
+                            "login": config.profile().login,
+                            "user_id": config.profile().user_id,
+                        }),
+                    );
+                }
+                writeln!(
+                    out,
+                    "{} {} ({})",
+                    "Switched to profile".bold(),
+                    config.active_profile.bold().green(),
+                    config.profile().login.bold()
+                )?;
+            }
+            AuthAction::Switch { profile: None } => {
+                auth::logout(&mut config).await?;
+                auth::login(&mut config, &show_notice).await?;
+                if !output.is_text() {
+                    return output::emit(
+                        &mut out,
+                        output,
+                        &json!({
+                            "profile": config.active_profile,
+                            "login": config.profile().login,
+                            "user_id": config.profile().user_id,
+                        }),
+                    );
+                }
+                writeln!(
+                    out,
+                    "{} {}",
+                    "Logged in as".bold(),
+                    config.profile().login.bold().green()
+                )?;
+            }
+        },
+        Action::Cache(cache_action) => {
+            let cache = Cache::new(&config)?;
+            match cache_action {
+                CacheAction::Stats => {
+                    let stats = cache.stats()?;
+                    if !output.is_text() {
+                        return output::emit(&mut out, output, &stats);
+                    }
+                    writeln!(
+                        out,
+                        "{} {} ({} expired, {} KiB)",
+                        "Cached responses:".bold(),
+                        stats.entries.to_string().bold().magenta(),
+                        stats.expired,
+                        stats.bytes.div_ceil(1024)
+                    )?;
+                    for (service, count) in &stats.by_service {
+                        writeln!(out, "{} {}", format!("- {service}:").bold(), count)?;
+                    }
+                    writeln!(out, "{} {}", "Location:".bold(), stats.path.blue())?;
+                }
+                CacheAction::Clear | CacheAction::Prune => {
+                    let clear = matches!(cache_action, CacheAction::Clear);
+                    let removed = if clear {
+                        cache.clear()?
+                    } else {
+                        cache.prune()?
+                    };
+                    if !output.is_text() {
+                        return output::emit(&mut out, output, &json!({ "removed": removed }));
+                    }
+                    writeln!(
+                        out,
+                        "{} {} {}",
+                        "Removed".bold(),
+                        removed.to_string().bold().magenta(),
+                        if clear {
+                            "cached responses"
+                        } else {
+                            "expired responses"
+                        }
+                        .bold()
+                    )?;
+                }
+            }
         }
     }
     Ok(())
 }
+
+#[cfg(any(feature = "ivr", feature = "helix"))]
+async fn render_template(
+    out: &mut impl Write,
+    template: &Template,
+    action: Action,
+    config: &Config,
+) -> Result<()> {
+    match action {
+        #[cfg(feature = "ivr")]
+        Action::User(user_action) => {
+            let user = leppunen::Api::new(config).user(user_action.user()).await?;
+            writeln!(out, "{}", template.render(&user)?)?;
+        }
+        #[cfg(feature = "chat")]
+        Action::Chat(ChatAction::Tail {
+            channel,
+            count,
+            filters,
+        }) => {
+            let mut config = config.clone();
+            tail_chat(&mut config, &channel, count, &filters, |message| {
+                writeln!(out, "{}", template.render(message)?)?;
+                Ok(())
+            })
+            .await?;
+        }
+        #[cfg(feature = "chat")]
+        Action::Chat(
+            ChatAction::Record { .. } | ChatAction::Say { .. } | ChatAction::Reply { .. },
+        ) => unreachable!("template_fields rejects --format for these"),
+        #[cfg(feature = "chat")]
+        Action::Chat(chat_action) => {
+            let chat = HelixClient::new(config)?
+                .get_chat(chat_action.channel())
+                .await?;
+            writeln!(out, "{}", template.render(&chat)?)?;
+        }
+        #[cfg(feature = "helix")]
+        Action::Vods { channel, amount } => {
+            let client = HelixClient::new(config)?;
+            let mut vods = pin!(client.get_vods(&channel, amount).await?);
+            while let Some(vod) = vods.try_next().await? {
+                writeln!(out, "{}", template.render(&vod)?)?;
+            }
+        }
+        #[cfg(feature = "helix")]
+        Action::Ll => {
+            let client = HelixClient::new(config)?;
+            let channels: Vec<_> = client
+                .get_live_followed_channels(&config.profile().user_id)
+                .try_collect()
+                .await?;
+            for channel in channels.iter().rev() {
+                writeln!(out, "{}", template.render(channel)?)?;
+            }
+        }
+        _ => unreachable!("template_fields rejects --format for this command"),
+    }
+    Ok(())
+}
+
+#[cfg(not(any(feature = "ivr", feature = "helix")))]
+async fn render_template(
+    _out: &mut impl Write,
+    _template: &Template,
+    _action: Action,
+    _config: &Config,
+) -> Result<()> {
+    unreachable!("template_fields rejects --format in this build")
+}
+
+/// The fields a `--format` template can use with `action`.
+#[cfg(any(feature = "ivr", feature = "helix"))]
+fn template_fields(action: &Action) -> Result<&'static [&'static str]> {
+    let fields = match action {
+        #[cfg(feature = "ivr")]
+        Action::User(_) => User::NAMES,
+        #[cfg(feature = "chat")]
+        Action::Chat(ChatAction::Tail { .. }) => ChatMessage::NAMES,
+        #[cfg(feature = "chat")]
+        Action::Chat(
+            ChatAction::Record { .. } | ChatAction::Say { .. } | ChatAction::Reply { .. },
+        ) => bail!(Error::Invalid(String::from(
+            "--format does not apply to chat record, say and reply"
+        ))),
+        #[cfg(feature = "chat")]
+        Action::Chat(_) => Chat::NAMES,
+        #[cfg(feature = "helix")]
+        Action::Vods { .. } => Vod::NAMES,
+        #[cfg(feature = "helix")]
+        Action::Ll => Channel::NAMES,
+        _ => bail!(Error::Invalid(String::from(
+            "--format is only supported by the user, chat, vods and ll commands"
+        ))),
+    };
+    Ok(fields)
+}
+
+#[cfg(not(any(feature = "ivr", feature = "helix")))]
+fn template_fields(_action: &Action) -> Result<&'static [&'static str]> {
+    bail!(Error::Invalid(String::from(
+        "--format needs the user, chat, vods or ll commands, which this build does not have"
+    )))
+}
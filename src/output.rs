@@ -0,0 +1,87 @@
+use crate::format;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::ArgEnum;
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::Write;
+
+#[derive(ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Jsonl,
+}
+
+impl OutputFormat {
+    pub fn is_text(&self) -> bool {
+        *self == OutputFormat::Text
+    }
+}
+
+/// Prints a single JSON document: pretty for `json`, one line for `jsonl`.
+pub fn emit<T: Serialize>(w: &mut impl Write, format: OutputFormat, value: &T) -> Result<()> {
+    match format {
+        OutputFormat::Json => writeln!(w, "{}", serde_json::to_string_pretty(value)?)?,
+        _ => writeln!(w, "{}", serde_json::to_string(value)?)?,
+    }
+    Ok(())
+}
+
+/// Prints a list as a JSON array for `json` or as one element per line for `jsonl`.
+pub fn emit_list<T: Serialize>(
+    w: &mut impl Write,
+    format: OutputFormat,
+    items: &[T],
+) -> Result<()> {
+    match format {
+        OutputFormat::Jsonl => {
+            for item in items {
+                writeln!(w, "{}", serde_json::to_string(item)?)?;
+            }
+        }
+        _ => writeln!(w, "{}", serde_json::to_string_pretty(items)?)?,
+    }
+    Ok(())
+}
+
+/// Marks everything in `printed` as answered from a cache entry fetched at `cached_at`: JSON
+/// objects, or each object of a list, get `cached_at` and `age_secs` fields, and every line
+/// of text ends with how long ago that was.
+pub fn mark_cached(
+    printed: &[u8],
+    format: OutputFormat,
+    cached_at: DateTime<Utc>,
+) -> Result<Vec<u8>> {
+    let age = Utc::now().signed_duration_since(cached_at);
+    let mut marked = Vec::new();
+    if format.is_text() {
+        let hms = format::duration_to_hms(age);
+        let hms = if hms.is_empty() { "0s" } else { hms.trim_end() };
+        let note = format!("(cached {hms} ago)").yellow();
+        for line in String::from_utf8_lossy(printed).lines() {
+            if line.trim().is_empty() {
+                writeln!(marked, "{line}")?;
+            } else {
+                writeln!(marked, "{line} {note}")?;
+            }
+        }
+        return Ok(marked);
+    }
+    let mark = |value: &mut Value| {
+        if let Value::Object(fields) = value {
+            fields.insert(String::from("cached_at"), cached_at.to_rfc3339().into());
+            fields.insert(String::from("age_secs"), age.num_seconds().into());
+        }
+    };
+    for value in serde_json::Deserializer::from_slice(printed).into_iter::<Value>() {
+        let mut value = value?;
+        match &mut value {
+            Value::Array(items) => items.iter_mut().for_each(mark),
+            value => mark(value),
+        }
+        emit(&mut marked, format, &value)?;
+    }
+    Ok(marked)
+}
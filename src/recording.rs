@@ -0,0 +1,161 @@
+use crate::error::{Error, Result};
+use reqwest::header::{self, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+use tracing::warn;
+
+const REDACTED: &str = "[redacted]";
+
+/// Number of the next exchange written by `--record`, continuing after the ones in the directory.
+static NEXT: OnceLock<AtomicUsize> = OnceLock::new();
+/// Exchanges `--replay` has not answered with yet, in the order they were recorded.
+static UNPLAYED: OnceLock<Mutex<Vec<Exchange>>> = OnceLock::new();
+
+/// Whether HTTP exchanges are saved to or answered from a directory, set by `--record`
+/// and `--replay` for a single run.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub enum Recording {
+    #[default]
+    Off,
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// A request and the response to it, stored as one JSON file per exchange.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Exchange {
+    pub service: String,
+    pub method: String,
+    pub url: String,
+    pub request_headers: BTreeMap<String, String>,
+    pub status: u16,
+    pub response_headers: BTreeMap<String, String>,
+    pub body: String,
+}
+
+impl Exchange {
+    /// A request to `url` and its response, with the token in the `Authorization` header
+    /// redacted.
+    pub fn new(
+        service: &str,
+        method: &str,
+        url: &str,
+        request_headers: &HeaderMap,
+        status: StatusCode,
+        response_headers: &HeaderMap,
+        body: &str,
+    ) -> Self {
+        let mut request_headers = to_map(request_headers);
+        if let Some(auth) = request_headers.get_mut(header::AUTHORIZATION.as_str()) {
+            *auth = String::from(REDACTED);
+        }
+        Self {
+            service: service.to_string(),
+            method: method.to_string(),
+            url: url.to_string(),
+            request_headers,
+            status: status.as_u16(),
+            response_headers: to_map(response_headers),
+            body: body.to_string(),
+        }
+    }
+
+    /// The recorded status, a status HTTP does not know is treated as a server error.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    pub fn response_headers(&self) -> HeaderMap {
+        self.response_headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes()).ok()?;
+                Some((name, HeaderValue::from_str(value).ok()?))
+            })
+            .collect()
+    }
+}
+
+/// Saves an exchange to `dir`, failing to do so only warns as the command itself went fine.
+pub fn record(dir: &Path, exchange: &Exchange) {
+    let next = NEXT.get_or_init(|| AtomicUsize::new(exchange_files(dir).len() + 1));
+    let n = next.fetch_add(1, Ordering::Relaxed);
+    let name = format!("{n:04}-{}.json", exchange.service.replace(' ', "-"));
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| fs::write(dir.join(name), serde_json::to_vec_pretty(exchange)?));
+    if let Err(e) = result {
+        warn!("Could not record the response from {}: {e}", exchange.url);
+    }
+}
+
+/// The next recorded response to a `method` request of `url` from `service`. Requests are
+/// matched by URL first and by path second, so recordings made with another account or
+/// endpoint still play.
+pub fn replay(dir: &Path, service: &str, method: &str, url: &str) -> Result<Exchange> {
+    let unplayed = match UNPLAYED.get() {
+        Some(unplayed) => unplayed,
+        None => {
+            let exchanges = load(dir)?;
+            UNPLAYED.get_or_init(|| Mutex::new(exchanges))
+        }
+    };
+    let mut unplayed = unplayed.lock().unwrap();
+    let candidates = || {
+        unplayed
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.service == service && e.method == method)
+    };
+    let index = candidates()
+        .find(|(_, e)| e.url == url)
+        .or_else(|| candidates().find(|(_, e)| path(&e.url) == path(url)))
+        .map(|(i, _)| i)
+        .ok_or_else(|| Error::NotRecorded(url.to_string()))?;
+    Ok(unplayed.remove(index))
+}
+
+fn load(dir: &Path) -> Result<Vec<Exchange>> {
+    let mut exchanges = Vec::new();
+    for file in exchange_files(dir) {
+        let Ok(json) = fs::read(&file) else {
+            continue;
+        };
+        let exchange = serde_json::from_slice(&json).map_err(|e| {
+            Error::Invalid(format!(
+                "{} is not a recorded exchange: {e}",
+                file.display()
+            ))
+        })?;
+        exchanges.push(exchange);
+    }
+    Ok(exchanges)
+}
+
+/// The exchange files in `dir`, oldest first.
+fn exchange_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn path(url: &str) -> Option<String> {
+    Url::parse(url).ok().map(|url| url.path().to_string())
+}
+
+fn to_map(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
@@ -0,0 +1,379 @@
+#[cfg(feature = "chat")]
+use crate::color;
+#[cfg(any(feature = "ivr", feature = "helix"))]
+use crate::format;
+#[cfg(feature = "helix")]
+use crate::helix::{channel::Channel, vod::Vod};
+#[cfg(feature = "chat")]
+use crate::irc::ChatMessage;
+#[cfg(feature = "ivr")]
+use crate::leppunen::user::{Badge, ChatSettings, Roles, User};
+#[cfg(feature = "chat")]
+use chrono::Local;
+#[cfg(feature = "helix")]
+use chrono::Utc;
+#[cfg(any(feature = "ivr", feature = "helix"))]
+use colored::Colorize;
+#[cfg(any(feature = "ivr", feature = "helix"))]
+use std::fmt;
+use std::io::{self, Write};
+
+/// A human readable view that can be written to any sink (stdout, a file, a test buffer, ...).
+pub trait Render {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()>;
+}
+
+pub trait CompactUser {
+    fn render(&self, w: &mut impl Write) -> io::Result<()>;
+}
+
+pub trait VerboseUser {
+    fn render(&self, w: &mut impl Write) -> io::Result<()>;
+}
+
+/// Renders a view into a `String` instead of a sink.
+pub fn to_string(view: &dyn Render) -> String {
+    let mut buf = Vec::new();
+    // writing into a Vec cannot fail
+    let _ = view.render(&mut buf);
+    String::from_utf8_lossy(&buf).into_owned()
+}
+
+#[cfg(any(feature = "ivr", feature = "helix"))]
+macro_rules! display_via_render {
+    ($($t:ty),*) => {
+        $(
+            impl fmt::Display for $t {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(&to_string(self))
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "ivr")]
+display_via_render!(Roles, Badge, ChatSettings);
+#[cfg(feature = "helix")]
+display_via_render!(Channel, Vod);
+
+#[cfg(feature = "ivr")]
+impl CompactUser for User {
+    fn render(&self, w: &mut impl Write) -> io::Result<()> {
+        let follows = format::readable_number(self.follows);
+        let followers = format::readable_number(self.followers);
+        let channel_views = format::readable_number(self.channel_views);
+
+        writeln!(
+            w,
+            "{}{}",
+            self.display_name_colored().bold(),
+            "'s profile information:".bold()
+        )?;
+        writeln!(w, "{} {}", "- User ID:".bold(), self.uid.bold().magenta())?;
+        writeln!(w, "{} {}", "- Banned:".bold(), yes_no(self.banned))?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Display name:".bold(),
+            self.display_name_colored().bold()
+        )?;
+        writeln!(w, "{} {}", "- Follows:".bold(), follows.bold().magenta())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Following:".bold(),
+            followers.bold().magenta()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Channel view:".bold(),
+            channel_views.bold().magenta()
+        )?;
+        writeln!(w, "{} {}", "- Chat color:".bold(), self.chat_color().bold())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Profile image:".bold(),
+            self.logo.bold().blue()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Account created:".bold(),
+            self.created_at
+                .date()
+                .naive_utc()
+                .to_string()
+                .bold()
+                .green()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Downtime:".bold(),
+            self.last_broadcast.time_since().bold().green()
+        )
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl VerboseUser for User {
+    fn render(&self, w: &mut impl Write) -> io::Result<()> {
+        let follows = format::readable_number(self.follows);
+        let followers = format::readable_number(self.followers);
+        let channel_views = format::readable_number(self.channel_views);
+
+        writeln!(
+            w,
+            "{}{}",
+            self.display_name_colored().bold(),
+            "'s profile information:".bold()
+        )?;
+        writeln!(w, "{} {}", "- User ID:".bold(), self.uid.bold().magenta())?;
+        writeln!(w, "{} {}", "- Banned:".bold(), yes_no(self.banned))?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Bio:".bold(),
+            self.bio.clone().unwrap_or_default().bold()
+        )?;
+        writeln!(w, "{} {}", "- Follows:".bold(), follows.bold().magenta())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Followers:".bold(),
+            followers.bold().magenta()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Channel views:".bold(),
+            channel_views.bold().magenta()
+        )?;
+        writeln!(w, "{} {}", "- Chat color:".bold(), self.chat_color().bold())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Profile picture:".bold(),
+            self.logo.bold().blue()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Verified bot:".bold(),
+            yes_no(self.verified_bot)
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Created at:".bold(),
+            self.created_at
+                .date()
+                .naive_utc()
+                .to_string()
+                .bold()
+                .green()
+        )?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Emote prefix:".bold(),
+            self.emote_prefix.bold()
+        )?;
+        writeln!(w, "{}", "- Roles:".bold())?;
+        self.roles.render(w)?;
+        writeln!(w, "{}", "- Badges:".bold())?;
+        for badge in self.badges.iter() {
+            badge.render(w)?;
+        }
+        writeln!(w, "{}", "- Chat Settings:".bold())?;
+        self.chat_settings.render(w)?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Downtime:".bold(),
+            self.last_broadcast.time_since().bold().green()
+        )
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl Render for Roles {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "  {} {}",
+            "- Affiliate:".bold(),
+            yes_no(self.is_affiliate)
+        )?;
+        writeln!(w, "  {} {}", "- Partner:".bold(), yes_no(self.is_partner))?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Staff:".bold(),
+            yes_no(self.is_staff.unwrap_or_default())
+        )
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl Render for Badge {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "  {} {}", "-".bold(), self.color().bold())
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl Render for ChatSettings {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(
+            w,
+            "  {} {}",
+            "- Chat delay:".bold(),
+            format::duration_to_hms(self.chat_delay_ms).bold().green()
+        )?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Followers only duration:".bold(),
+            format::duration_to_hms(self.followers_only_duration_minutes)
+                .bold()
+                .green()
+        )?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Slow mode duration:".bold(),
+            format::duration_to_hms(self.slow_mode_duration_seconds)
+                .bold()
+                .green()
+        )?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Emote only:".bold(),
+            yes_no(self.emote_only)
+        )?;
+        writeln!(w, "  {} {}", "- Sub only:".bold(), yes_no(self.sub_only))?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Unique chat:".bold(),
+            yes_no(self.unique_chat)
+        )?;
+        writeln!(
+            w,
+            "  {} {}",
+            "- Require verified account:".bold(),
+            yes_no(self.require_verified_account)
+        )?;
+        writeln!(w, "  {}", "- Chat rules:".bold())?;
+        for rule in self.chat_rules.iter() {
+            writeln!(w, "    {} {}", "-".bold(), rule.bold().yellow())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "helix")]
+impl Render for Channel {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        if !self.live {
+            return writeln!(
+                w,
+                "{} {} {}",
+                "■".bold(),
+                self.user_name.bold(),
+                "is offline.".bold()
+            );
+        }
+        let uptime = Utc::now().signed_duration_since(self.started_at);
+        let out = format!(
+            "► {} is live playing {} to {} viewers:\n{} {}\n{} {}\n{} {}{}",
+            self.user_name,
+            self.game_name,
+            self.viewer_count.to_string().magenta(),
+            "- Title:".white(),
+            self.title.white(),
+            "- Uptime:".white(),
+            format::duration_to_hms(uptime).green(),
+            "- URL:".white(),
+            "https://twitch.tv/".blue(),
+            self.user_login.blue()
+        );
+        writeln!(w, "{}", out.bold().red())
+    }
+}
+
+#[cfg(feature = "helix")]
+impl Render for Vod {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        writeln!(w, "{} {}", "- Title:".bold(), self.title.bold().green())?;
+        writeln!(w, "{} {}", "- URL:".bold(), self.url.bold().blue())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- View count:".bold(),
+            format::readable_number(self.view_count).bold().magenta()
+        )?;
+        writeln!(w, "{} {}", "- Type:".bold(), self.vod_type.bold().green())?;
+        writeln!(
+            w,
+            "{} {}",
+            "- Duration:".bold(),
+            self.duration.bold().green()
+        )
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Render for ChatMessage {
+    fn render(&self, w: &mut dyn Write) -> io::Result<()> {
+        let time = self.sent_at.with_timezone(&Local).format("%H:%M:%S");
+        let rgb = format::hex_to_rgb(&self.color);
+        let name = match rgb {
+            Some(rgb) => color::rgb(&self.display_name, rgb),
+            None => self.display_name.clone(),
+        };
+        let badges: String = self
+            .badges
+            .iter()
+            .map(|badge| format!("[{}] ", badge_label(badge)))
+            .collect();
+        let prefix = format!("{} {}", time.to_string().dimmed(), badges.dimmed());
+        if self.action {
+            // `/me` messages are drawn in the sender's color, like in the Twitch chat
+            let text = match rgb {
+                Some(rgb) => color::rgb(&self.text, rgb),
+                None => self.text.italic().to_string(),
+            };
+            return writeln!(w, "{prefix}{} {text}", name.bold());
+        }
+        if self.highlighted {
+            let text = self.text.bold().black().on_bright_yellow();
+            return writeln!(w, "{prefix}{}: {text}", name.bold());
+        }
+        writeln!(w, "{prefix}{}: {}", name.bold(), self.text)
+    }
+}
+
+#[cfg(feature = "chat")]
+fn badge_label(badge: &str) -> &str {
+    match badge {
+        "moderator" => "mod",
+        "subscriber" => "sub",
+        "partner" => "verified",
+        other => other,
+    }
+}
+
+#[cfg(feature = "ivr")]
+fn yes_no(b: bool) -> String {
+    if b {
+        "yes".bold().green().to_string()
+    } else {
+        "no".bold().red().to_string()
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{anyhow, bail, Context, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+#[cfg(feature = "interactive-config")]
+use dialoguer::{theme::ColorfulTheme, Password};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+const SERVICE: &str = "twitch-lookup";
+
+/// Where tokens and the client secret are kept instead of the plaintext config file.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretStoreKind {
+    /// The OS keyring when the system has one, the encrypted file otherwise.
+    #[default]
+    Auto,
+    Keyring,
+    File,
+}
+
+pub trait SecretStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+static STORE: OnceLock<Box<dyn SecretStore>> = OnceLock::new();
+
+/// Opens the store of the given kind once per process, so a passphrase is asked for only once.
+pub fn store(kind: SecretStoreKind) -> Result<&'static dyn SecretStore> {
+    if let Some(store) = STORE.get() {
+        return Ok(store.as_ref());
+    }
+    let store: Box<dyn SecretStore> = match kind {
+        #[cfg(feature = "keyring")]
+        SecretStoreKind::Keyring => Box::new(KeyringStore::open()?),
+        #[cfg(not(feature = "keyring"))]
+        SecretStoreKind::Keyring => {
+            bail!("This build cannot use the OS keyring, set secret_store to \"file\" instead")
+        }
+        SecretStoreKind::File => Box::new(FileStore::open()?),
+        #[cfg(feature = "keyring")]
+        SecretStoreKind::Auto => match KeyringStore::probe()? {
+            Some(keyring) => Box::new(keyring),
+            None => Box::new(FileStore::open()?),
+        },
+        #[cfg(not(feature = "keyring"))]
+        SecretStoreKind::Auto => Box::new(FileStore::open()?),
+    };
+    Ok(STORE.get_or_init(|| store).as_ref())
+}
+
+/// Secrets kept in the platform keyring (Secret Service on Linux).
+#[cfg(feature = "keyring")]
+pub struct KeyringStore;
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    fn open() -> Result<Self> {
+        Self::probe()?.ok_or_else(|| anyhow!("This system has no OS keyring"))
+    }
+
+    /// The keyring, `None` when the system has none at all. A keyring that exists but cannot
+    /// be used right now (e.g. it is locked) is an error rather than a reason to keep the
+    /// secrets somewhere else.
+    fn probe() -> Result<Option<Self>> {
+        // a lookup tells apart "no keyring" from "no such entry"
+        match keyring::Entry::new(SERVICE, "probe")?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(Some(Self)),
+            Err(keyring::Error::PlatformFailure(e)) => {
+                tracing::debug!("no OS keyring: {e}");
+                Ok(None)
+            }
+            Err(e) => Err(anyhow!("The OS keyring is not available: {e}")),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeyringStore {
+    fn name(&self) -> &'static str {
+        "the OS keyring"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match keyring::Entry::new(SERVICE, key)?.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        keyring::Entry::new(SERVICE, key)?.set_password(value)?;
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match keyring::Entry::new(SERVICE, key)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// On-disk layout of the encrypted secrets file.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+/// Secrets kept in a file encrypted with ChaCha20-Poly1305 under a key derived from a
+/// passphrase with Argon2id. The passphrase is read from `TL_PASSPHRASE` or prompted for.
+pub struct FileStore {
+    path: PathBuf,
+    key: Key,
+    salt: [u8; 16],
+    secrets: Mutex<BTreeMap<String, String>>,
+}
+
+impl FileStore {
+    fn open() -> Result<Self> {
+        let path = dirs::config_dir()
+            .ok_or_else(|| anyhow!("Could not find the config directory"))?
+            .join(SERVICE)
+            .join("secrets.enc");
+        if !path.exists() {
+            let passphrase = passphrase(true)?;
+            let salt: [u8; 16] = rand_bytes();
+            return Ok(Self {
+                key: derive_key(&passphrase, &salt)?,
+                path,
+                salt,
+                secrets: Mutex::new(BTreeMap::new()),
+            });
+        }
+        let sealed: Sealed = serde_json::from_slice(&fs::read(&path)?)
+            .with_context(|| format!("{} is corrupted", path.display()))?;
+        let salt: [u8; 16] = STANDARD
+            .decode(&sealed.salt)?
+            .try_into()
+            .map_err(|_| anyhow!("{} is corrupted", path.display()))?;
+        let key = derive_key(&passphrase(false)?, &salt)?;
+        let nonce: [u8; 12] = STANDARD
+            .decode(&sealed.nonce)?
+            .try_into()
+            .map_err(|_| anyhow!("{} is corrupted", path.display()))?;
+        let plaintext = ChaCha20Poly1305::new(&key)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                STANDARD.decode(&sealed.data)?.as_ref(),
+            )
+            .map_err(|_| anyhow!("Wrong passphrase for {}", path.display()))?;
+        Ok(Self {
+            path,
+            key,
+            salt,
+            secrets: Mutex::new(serde_json::from_slice(&plaintext)?),
+        })
+    }
+
+    fn write(&self, secrets: &BTreeMap<String, String>) -> Result<()> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let data = ChaCha20Poly1305::new(&self.key)
+            .encrypt(&nonce, serde_json::to_vec(secrets)?.as_ref())
+            .map_err(|_| anyhow!("Could not encrypt the secrets"))?;
+        let sealed = Sealed {
+            salt: STANDARD.encode(self.salt),
+            nonce: STANDARD.encode(nonce),
+            data: STANDARD.encode(data),
+        };
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // only the user may read the secrets, even encrypted
+        write_private(&self.path, &serde_json::to_vec(&sealed)?)?;
+        Ok(())
+    }
+}
+
+/// Replaces the file at `path` with `contents`, readable only by the user. The contents go to
+/// a temporary file next to it first, so a crash or a full disk leaves the old file intact.
+pub(crate) fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.tmp", std::process::id()));
+    let temp = path.with_file_name(name);
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let written = options.open(&temp).and_then(|mut file| {
+        #[cfg(unix)]
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    match written.and_then(|()| fs::rename(&temp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+impl SecretStore for FileStore {
+    fn name(&self) -> &'static str {
+        "the encrypted secrets file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.secrets.lock().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut secrets = self.secrets.lock().unwrap();
+        if secrets.get(key).map(String::as_str) == Some(value) {
+            return Ok(());
+        }
+        secrets.insert(key.to_string(), value.to_string());
+        self.write(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut secrets = self.secrets.lock().unwrap();
+        if secrets.remove(key).is_some() {
+            self.write(&secrets)?;
+        }
+        Ok(())
+    }
+}
+
+fn passphrase(new: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var("TL_PASSPHRASE") {
+        if passphrase.is_empty() {
+            bail!("TL_PASSPHRASE is empty, set it to the passphrase or unset it to be asked");
+        }
+        return Ok(passphrase);
+    }
+    prompt_passphrase(new)
+}
+
+#[cfg(not(feature = "interactive-config"))]
+fn prompt_passphrase(_new: bool) -> Result<String> {
+    bail!("Set TL_PASSPHRASE to unlock the secrets file, this build cannot prompt for it")
+}
+
+#[cfg(feature = "interactive-config")]
+fn prompt_passphrase(new: bool) -> Result<String> {
+    let theme = ColorfulTheme::default();
+    let mut prompt = Password::with_theme(&theme);
+    if new {
+        prompt
+            .with_prompt("Choose a passphrase to encrypt your Twitch credentials")
+            .with_confirmation("Repeat the passphrase", "The passphrases do not match");
+    } else {
+        prompt.with_prompt("Passphrase for your Twitch credentials");
+    }
+    let passphrase = prompt.interact()?;
+    if passphrase.is_empty() {
+        bail!("The passphrase cannot be empty");
+    }
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Could not derive the encryption key: {e}"))?;
+    Ok(key)
+}
+
+/// Random bytes from the OS, for salts and the OAuth state.
+pub(crate) fn rand_bytes<const N: usize>() -> [u8; N] {
+    use chacha20poly1305::aead::rand_core::RngCore;
+    let mut bytes = [0u8; N];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
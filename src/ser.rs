@@ -0,0 +1,23 @@
+use chrono::Duration;
+use serde::Serializer;
+
+pub fn serialize_millis<S>(d: &Duration, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_i64(d.num_milliseconds())
+}
+
+pub fn serialize_seconds<S>(d: &Duration, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_i64(d.num_seconds())
+}
+
+pub fn serialize_minutes<S>(d: &Duration, s: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    s.serialize_i64(d.num_minutes())
+}
@@ -0,0 +1,398 @@
+use crate::error::Error;
+use crate::format;
+#[cfg(feature = "chat")]
+use crate::helix::chat::Chat;
+#[cfg(feature = "helix")]
+use crate::helix::{channel::Channel, vod::Vod};
+#[cfg(feature = "chat")]
+use crate::irc::ChatMessage;
+#[cfg(feature = "ivr")]
+use crate::leppunen::user::User;
+use anyhow::{bail, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Duration, Utc};
+
+/// A value a template placeholder can resolve to.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Str(String),
+    Num(i64),
+    Bool(bool),
+    Date(DateTime<Utc>),
+    Duration(Duration),
+    List(Vec<String>),
+    Null,
+}
+
+/// Exposes the fields of a model to templates by name.
+pub trait Fields {
+    /// Every name `field` answers to, so templates can be checked before anything is fetched.
+    const NAMES: &'static [&'static str];
+
+    fn field(&self, name: &str) -> Option<Value>;
+}
+
+/// The filters placeholders can use, in the order they are documented.
+const FILTERS: &[&str] = &["num", "hms", "since", "upper", "lower", "len"];
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Placeholder {
+        name: String,
+        filters: Vec<String>,
+        spec: Option<String>,
+    },
+}
+
+/// A parsed output template such as `{display_name} {followers|num} {created_at:%Y}`.
+///
+/// Placeholders are `{field}`, optionally followed by `|filter`s and a `:spec`.
+/// Filters: `num` (digit grouping), `hms` (durations, numbers are taken as seconds),
+/// `since` (time elapsed since a date), `upper`, `lower`, `len`.
+/// The spec is a strftime format for dates. `{{` and `}}` print literal braces.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = src.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut body = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => body.push(c),
+                            None => bail!(Error::Invalid(format!(
+                                "Unclosed placeholder `{{{body}` in template"
+                            ))),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_placeholder(&body)?);
+                }
+                '}' => bail!(Error::Invalid(String::from(
+                    "Unmatched `}` in template, use `}}` for a literal brace"
+                ))),
+                '\\' if chars.peek() == Some(&'n') => {
+                    chars.next();
+                    literal.push('\n');
+                }
+                '\\' if chars.peek() == Some(&'t') => {
+                    chars.next();
+                    literal.push('\t');
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Self { segments })
+    }
+
+    /// Fails on placeholders naming a field that is not among `fields`.
+    pub fn check(&self, fields: &[&str]) -> Result<()> {
+        for segment in &self.segments {
+            if let Segment::Placeholder { name, .. } = segment {
+                if !fields.contains(&name.as_str()) {
+                    bail!(Error::Invalid(format!(
+                        "Unknown field `{name}`, available fields: {}",
+                        fields.join(", ")
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render<T: Fields>(&self, item: &T) -> Result<String> {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => out.push_str(s),
+                Segment::Placeholder {
+                    name,
+                    filters,
+                    spec,
+                } => {
+                    let mut value = item.field(name).ok_or_else(|| {
+                        Error::Invalid(format!(
+                            "Unknown field `{name}`, available fields: {}",
+                            T::NAMES.join(", ")
+                        ))
+                    })?;
+                    for filter in filters {
+                        value = apply_filter(value, filter)?;
+                    }
+                    out.push_str(&display(value, spec.as_deref()));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn parse_placeholder(body: &str) -> Result<Segment> {
+    let (head, spec) = match body.split_once(':') {
+        Some((head, spec)) => (head, Some(spec.to_string())),
+        None => (body, None),
+    };
+    if let Some(spec) = &spec {
+        if StrftimeItems::new(spec).any(|item| matches!(item, Item::Error)) {
+            bail!(Error::Invalid(format!(
+                "Invalid date format `{spec}` in placeholder `{{{body}}}`"
+            )));
+        }
+    }
+    let mut parts = head.split('|').map(str::trim);
+    let name = parts.next().unwrap_or_default().to_string();
+    if name.is_empty() {
+        bail!(Error::Invalid(format!(
+            "Empty placeholder `{{{body}}}` in template"
+        )));
+    }
+    let filters: Vec<String> = parts.map(String::from).collect();
+    if let Some(filter) = filters.iter().find(|f| !FILTERS.contains(&f.as_str())) {
+        bail!(Error::Invalid(format!(
+            "Unknown filter `{filter}` in placeholder `{{{body}}}`, available filters: {}",
+            FILTERS.join(", ")
+        )));
+    }
+    Ok(Segment::Placeholder {
+        name,
+        filters,
+        spec,
+    })
+}
+
+fn apply_filter(value: Value, filter: &str) -> Result<Value> {
+    let value = match (filter, value) {
+        ("num", Value::Num(n)) => Value::Str(group_digits(n)),
+        ("hms", Value::Num(n)) => Value::Str(format::duration_to_hms(Duration::seconds(n))),
+        ("hms", Value::Duration(d)) => Value::Str(format::duration_to_hms(d)),
+        ("since", Value::Date(d)) => Value::Duration(Utc::now().signed_duration_since(d)),
+        ("upper", Value::Str(s)) => Value::Str(s.to_uppercase()),
+        ("lower", Value::Str(s)) => Value::Str(s.to_lowercase()),
+        ("len", Value::List(l)) => Value::Num(l.len() as i64),
+        ("len", Value::Str(s)) => Value::Num(s.chars().count() as i64),
+        (filter, value) => bail!(Error::Invalid(format!(
+            "Filter `{filter}` cannot be applied to {value:?}"
+        ))),
+    };
+    Ok(value)
+}
+
+fn group_digits(n: i64) -> String {
+    let grouped = format::readable_number(n.unsigned_abs());
+    if n < 0 {
+        format!("-{grouped}")
+    } else {
+        grouped
+    }
+}
+
+fn display(value: Value, spec: Option<&str>) -> String {
+    match value {
+        Value::Str(s) => s,
+        Value::Num(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Date(d) => match spec {
+            Some(spec) => d.format(spec).to_string(),
+            None => d.to_rfc3339(),
+        },
+        Value::Duration(d) => format::duration_to_hms(d),
+        Value::List(l) => l.join(spec.unwrap_or(", ")),
+        Value::Null => String::new(),
+    }
+}
+
+#[cfg(feature = "ivr")]
+impl Fields for User {
+    const NAMES: &'static [&'static str] = &[
+        "id",
+        "login",
+        "display_name",
+        "banned",
+        "bio",
+        "follows",
+        "followers",
+        "channel_views",
+        "chat_color",
+        "logo",
+        "verified_bot",
+        "created_at",
+        "emote_prefix",
+        "roles",
+        "badges",
+        "chat_delay",
+        "followers_only_duration",
+        "slow_mode_duration",
+        "emote_only",
+        "sub_only",
+        "unique_chat",
+        "chat_rules",
+        "last_broadcast",
+        "last_broadcast_title",
+        "downtime",
+    ];
+
+    fn field(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "id" => Value::Str(self.uid.clone()),
+            "login" => Value::Str(self.login.clone()),
+            "display_name" => Value::Str(self.display_name.clone()),
+            "banned" => Value::Bool(self.banned),
+            "bio" => self.bio.clone().map_or(Value::Null, Value::Str),
+            "follows" => Value::Num(self.follows.into()),
+            "followers" => Value::Num(self.followers.into()),
+            "channel_views" => Value::Num(self.channel_views.into()),
+            "chat_color" => Value::Str(self.chat_color.clone()),
+            "logo" => Value::Str(self.logo.clone()),
+            "verified_bot" => Value::Bool(self.verified_bot),
+            "created_at" => Value::Date(self.created_at),
+            "emote_prefix" => Value::Str(self.emote_prefix.clone()),
+            "roles" => Value::List(self.roles.get_active()),
+            "badges" => Value::List(self.badges.iter().map(|b| b.title.clone()).collect()),
+            "chat_delay" => Value::Duration(self.chat_settings.chat_delay_ms),
+            "followers_only_duration" => {
+                Value::Duration(self.chat_settings.followers_only_duration_minutes)
+            }
+            "slow_mode_duration" => Value::Duration(self.chat_settings.slow_mode_duration_seconds),
+            "emote_only" => Value::Bool(self.chat_settings.emote_only),
+            "sub_only" => Value::Bool(self.chat_settings.sub_only),
+            "unique_chat" => Value::Bool(self.chat_settings.unique_chat),
+            "chat_rules" => Value::List(self.chat_settings.chat_rules.clone()),
+            "last_broadcast" => Value::Date(self.last_broadcast.started_at),
+            "last_broadcast_title" => self
+                .last_broadcast
+                .title
+                .clone()
+                .map_or(Value::Null, Value::Str),
+            "downtime" => {
+                Value::Duration(Utc::now().signed_duration_since(self.last_broadcast.started_at))
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(feature = "helix")]
+impl Fields for Channel {
+    const NAMES: &'static [&'static str] = &[
+        "user_name",
+        "user_login",
+        "game_name",
+        "live",
+        "title",
+        "viewer_count",
+        "started_at",
+        "uptime",
+        "url",
+    ];
+
+    fn field(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "user_name" => Value::Str(self.user_name.clone()),
+            "user_login" => Value::Str(self.user_login.clone()),
+            "game_name" => Value::Str(self.game_name.clone()),
+            "live" => Value::Bool(self.live),
+            "title" => Value::Str(self.title.clone()),
+            "viewer_count" => Value::Num(self.viewer_count.into()),
+            "started_at" => Value::Date(self.started_at),
+            "uptime" => Value::Duration(Utc::now().signed_duration_since(self.started_at)),
+            "url" => Value::Str(format!("https://twitch.tv/{}", self.user_login)),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(feature = "helix")]
+impl Fields for Vod {
+    const NAMES: &'static [&'static str] = &["title", "url", "view_count", "type", "duration"];
+
+    fn field(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "title" => Value::Str(self.title.clone()),
+            "url" => Value::Str(self.url.clone()),
+            "view_count" => Value::Num(self.view_count.into()),
+            "type" => Value::Str(self.vod_type.clone()),
+            "duration" => Value::Str(self.duration.clone()),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Fields for Chat {
+    const NAMES: &'static [&'static str] = &[
+        "chatter_count",
+        "broadcaster",
+        "moderators",
+        "vips",
+        "viewers",
+    ];
+
+    fn field(&self, name: &str) -> Option<Value> {
+        let chatters = self.chatters();
+        let value = match name {
+            "chatter_count" => Value::Num(self.chatter_count().into()),
+            "broadcaster" => Value::Str(chatters.broadcaster().to_string()),
+            "moderators" => Value::List(chatters.moderators().clone()),
+            "vips" => Value::List(chatters.vips().clone()),
+            "viewers" => Value::List(chatters.viewers().clone()),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+#[cfg(feature = "chat")]
+impl Fields for ChatMessage {
+    const NAMES: &'static [&'static str] = &[
+        "channel",
+        "login",
+        "display_name",
+        "color",
+        "badges",
+        "text",
+        "action",
+        "sent_at",
+        "highlighted",
+    ];
+
+    fn field(&self, name: &str) -> Option<Value> {
+        let value = match name {
+            "channel" => Value::Str(self.channel.clone()),
+            "login" => Value::Str(self.login.clone()),
+            "display_name" => Value::Str(self.display_name.clone()),
+            "color" => Value::Str(self.color.clone()),
+            "badges" => Value::List(self.badges.clone()),
+            "text" => Value::Str(self.text.clone()),
+            "action" => Value::Bool(self.action),
+            "sent_at" => Value::Date(self.sent_at),
+            "highlighted" => Value::Bool(self.highlighted),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
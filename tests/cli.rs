@@ -0,0 +1,1271 @@
+//! End-to-end runs of `tl` against a local stand-in for every backend.
+
+mod common;
+
+use common::{Route, Sandbox};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+fn gunzip(path: &Path) -> String {
+    let mut text = String::new();
+    MultiGzDecoder::new(fs::File::open(path).unwrap())
+        .read_to_string(&mut text)
+        .unwrap();
+    text
+}
+
+// ivr.fi
+
+#[test]
+fn user_id() {
+    Sandbox::new()
+        .run(&["user", "id", "forsen"])
+        .assert_snapshot("user_id");
+}
+
+#[test]
+fn user_compact_json() {
+    Sandbox::new()
+        .run(&["--output", "json", "user", "compact", "forsen"])
+        .assert_snapshot("user_compact_json");
+}
+
+#[test]
+fn user_banned() {
+    Sandbox::new()
+        .run(&["user", "bc", "banned_streamer"])
+        .assert_snapshot("user_banned");
+}
+
+#[test]
+fn user_chat_settings() {
+    Sandbox::new()
+        .run(&["user", "cs", "forsen"])
+        .assert_snapshot("user_chat_settings");
+}
+
+#[test]
+fn user_unknown() {
+    Sandbox::new()
+        .run(&["user", "id", "nobody_here"])
+        .assert_snapshot("user_unknown");
+}
+
+#[test]
+fn user_invalid_name() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["user", "id", "ab"])
+        .assert_snapshot("user_invalid_name");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn user_invalid_date_format() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["--format", "{created_at:%Q}", "user", "compact", "forsen"])
+        .assert_snapshot("user_invalid_date_format");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn user_unknown_field() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["--format", "{name|upper}", "user", "compact", "forsen"])
+        .assert_snapshot("user_unknown_field");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn vods_unknown_filter() {
+    let sandbox = Sandbox::logged_in();
+    sandbox
+        .run(&["--format", "{title|shout}", "vods", "forsen", "2"])
+        .assert_snapshot("vods_unknown_filter");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn user_schema_change() {
+    let sandbox = Sandbox::new();
+    sandbox.server.mount(Route::new(
+        "GET /ivr/twitch/user/forsen",
+        200,
+        r#"{"id":22484632}"#,
+    ));
+    sandbox
+        .run(&["user", "id", "forsen"])
+        .assert_snapshot("user_schema_change");
+}
+
+#[test]
+fn user_server_error() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .server
+        .mount(Route::new("GET /ivr/twitch/user/forsen", 500, "oops"));
+    sandbox
+        .run(&["user", "id", "forsen"])
+        .assert_snapshot("user_server_error");
+}
+
+#[test]
+fn logs_json() {
+    Sandbox::new()
+        .run(&["--output", "json", "logs", "tester", "forsen"])
+        .assert_snapshot("logs_json");
+}
+
+#[test]
+fn logs_not_found() {
+    Sandbox::new()
+        .run(&["logs", "nobody_here", "forsen"])
+        .assert_snapshot("logs_not_found");
+}
+
+// DecAPI
+
+#[test]
+fn followage() {
+    Sandbox::new()
+        .run(&["fa", "tester", "forsen"])
+        .assert_snapshot("followage");
+}
+
+#[test]
+fn followage_json() {
+    Sandbox::new()
+        .run(&["--output", "json", "fa", "lurker", "forsen"])
+        .assert_snapshot("followage_json");
+}
+
+#[test]
+fn followage_unknown() {
+    Sandbox::new()
+        .run(&["fa", "nobody_here", "forsen"])
+        .assert_snapshot("followage_unknown");
+}
+
+#[test]
+fn title() {
+    Sandbox::new()
+        .run(&["title", "forsen"])
+        .assert_snapshot("title");
+}
+
+#[test]
+fn live() {
+    Sandbox::new()
+        .run(&["live", "forsen"])
+        .assert_snapshot("live");
+}
+
+#[test]
+fn live_offline_json() {
+    Sandbox::new()
+        .run(&["--output", "json", "live", "nymn"])
+        .assert_snapshot("live_offline_json");
+}
+
+// Helix
+
+#[test]
+fn chat_count() {
+    Sandbox::logged_in()
+        .run(&["chat", "count", "forsen"])
+        .assert_snapshot("chat_count");
+}
+
+#[test]
+fn chat_mods() {
+    Sandbox::logged_in()
+        .run(&["chat", "mods", "forsen"])
+        .assert_snapshot("chat_mods");
+}
+
+#[test]
+fn chat_present_json() {
+    Sandbox::logged_in()
+        .run(&["--output", "json", "chat", "present", "pajlada", "forsen"])
+        .assert_snapshot("chat_present_json");
+}
+
+#[test]
+fn chat_roles() {
+    Sandbox::logged_in()
+        .run(&[
+            "--format",
+            "{broadcaster} | {moderators} | {vips} | {viewers}",
+            "chat",
+            "count",
+            "forsen",
+        ])
+        .assert_snapshot("chat_roles");
+}
+
+#[test]
+fn chat_not_a_moderator() {
+    let sandbox = Sandbox::logged_in();
+    sandbox.server.mount(Route::new(
+        "GET /helix/chat/chatters?broadcaster_id=22484632&moderator_id=123&first=1000",
+        403,
+        r#"{"error":"Forbidden","status":403,"message":"user 123 is not one of the broadcaster's moderators"}"#,
+    ));
+    sandbox
+        .run(&["chat", "mods", "forsen"])
+        .assert_snapshot("chat_not_a_moderator");
+}
+
+#[test]
+fn chat_tail() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["chat", "tail", "forsen", "-n", "4"])
+        .assert_snapshot("chat_tail");
+    let received = sandbox.irc.received();
+    let joins = received.iter().filter(|l| *l == "JOIN #forsen").count();
+    assert_eq!(joins, 2, "{received:?}");
+    assert!(received.contains(&String::from("PONG :tmi.twitch.tv")));
+    assert!(received.iter().any(|l| l.starts_with("NICK justinfan")));
+    assert!(!received.iter().any(|l| l.starts_with("PASS ")));
+}
+
+#[test]
+fn chat_tail_jsonl() {
+    Sandbox::new()
+        .run(&["--output", "jsonl", "chat", "tail", "Forsen", "-n", "2"])
+        .assert_snapshot("chat_tail_jsonl");
+}
+
+#[test]
+fn chat_tail_template() {
+    Sandbox::new()
+        .run(&[
+            "--format",
+            "{display_name} ({badges}): {text}",
+            "chat",
+            "tail",
+            "forsen",
+            "-n",
+            "3",
+        ])
+        .assert_snapshot("chat_tail_template");
+}
+
+#[test]
+fn chat_tail_connection_lost() {
+    Sandbox::new()
+        .run(&["chat", "tail", "forsen"])
+        .assert_snapshot("chat_tail_connection_lost");
+}
+
+#[test]
+fn chat_tail_filters() {
+    Sandbox::new()
+        .run(&[
+            "chat",
+            "tail",
+            "forsen",
+            "--exclude-bots",
+            "--grep",
+            "(?i)waves|again",
+            "-n",
+            "2",
+        ])
+        .assert_snapshot("chat_tail_filters");
+}
+
+#[test]
+fn chat_tail_highlights() {
+    Sandbox::logged_in()
+        .run(&[
+            "--format",
+            "{highlighted} {login}: {text}",
+            "chat",
+            "tail",
+            "forsen",
+            "--mods-only",
+            "--highlight",
+            "STREAM",
+            "-n",
+            "2",
+        ])
+        .assert_snapshot("chat_tail_highlights");
+}
+
+#[test]
+fn chat_tail_mentions() {
+    Sandbox::logged_in()
+        .run(&[
+            "--output",
+            "jsonl",
+            "chat",
+            "tail",
+            "forsen",
+            "--mentions",
+            "-n",
+            "1",
+        ])
+        .assert_snapshot("chat_tail_mentions");
+}
+
+#[test]
+fn chat_tail_mentions_logged_out() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["chat", "tail", "forsen", "--mentions"])
+        .assert_snapshot("chat_tail_mentions_logged_out");
+    assert!(sandbox.irc.received().is_empty());
+}
+
+#[test]
+fn chat_tail_config_rules() {
+    let log = tempfile::tempdir().unwrap();
+    let log = log.path().join("hook.log");
+    let sandbox = Sandbox::with_config(&format!(
+        r#"
+[chat]
+ignore_users = ["Lurker"]
+ignore = ["^forsenE$"]
+highlights = ["again"]
+hook = 'echo "$TL_CHAT_LOGIN in $TL_CHAT_CHANNEL: $TL_CHAT_TEXT" >> {}'
+"#,
+        log.display()
+    ));
+    sandbox
+        .run(&["chat", "tail", "forsen", "-n", "2"])
+        .assert_snapshot("chat_tail_config_rules");
+    // the hook runs in the background
+    for _ in 0..50 {
+        if log.exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let hooked = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(hooked, "tester in forsen: back again\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn chat_tail_reaps_hooks() {
+    let log = tempfile::tempdir().unwrap();
+    let log = log.path().join("hook.log");
+    let sandbox = Sandbox::with_config(&format!(
+        r#"
+[chat]
+highlights = ["again"]
+hook = 'echo "$TL_CHAT_TEXT" >> {}'
+"#,
+        log.display()
+    ));
+    // the chat stays open after two highlighted messages
+    sandbox.irc.play_fixture("irc/hooks.irc");
+    let mut tail = sandbox.spawn(&["chat", "tail", "forsen"]);
+    for _ in 0..50 {
+        if read(&log).lines().count() == 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    thread::sleep(Duration::from_millis(500));
+    assert!(tail.try_wait().unwrap().is_none(), "chat tail exited early");
+    // children that exited but were never waited for stay in the process table as zombies
+    let zombies: Vec<_> = fs::read_dir("/proc")
+        .unwrap()
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join("stat")).ok())
+        .filter(|stat| {
+            let fields: Vec<_> = stat
+                .rsplit_once(')')
+                .unwrap()
+                .1
+                .split_whitespace()
+                .collect();
+            fields[0] == "Z" && fields[1] == tail.id().to_string()
+        })
+        .collect();
+    tail.kill().unwrap();
+    tail.wait().unwrap();
+    assert_eq!(read(&log), "back again\nand again\n");
+    assert!(zombies.is_empty(), "{zombies:?}");
+}
+
+#[test]
+fn chat_tail_invalid_grep() {
+    Sandbox::new()
+        .run(&["chat", "tail", "forsen", "--grep", "(unclosed"])
+        .assert_snapshot("chat_tail_invalid_grep");
+}
+
+#[test]
+fn chat_record() {
+    let sandbox = Sandbox::new();
+    sandbox.irc.play_fixture("irc/record.irc");
+    let dir = sandbox.path("chat");
+    let forsen = dir.join("forsen");
+    fs::create_dir_all(&forsen).unwrap();
+    fs::write(forsen.join("2022-03-13.irc"), "left by an earlier run\n").unwrap();
+    // a day an earlier run already compressed, which late lines are added to
+    let mut earlier = GzEncoder::new(
+        fs::File::create(forsen.join("2022-03-14.irc.gz")).unwrap(),
+        Compression::default(),
+    );
+    earlier
+        .write_all(b"compressed by an earlier run\n")
+        .unwrap();
+    earlier.finish().unwrap();
+
+    let mut recorder = sandbox.spawn(&[
+        "chat",
+        "record",
+        "forsen",
+        "NymN",
+        "--dir",
+        dir.to_str().unwrap(),
+    ]);
+    // the second day arrives after the recorder reconnected
+    let today = forsen.join("2022-03-15.jsonl");
+    for _ in 0..100 {
+        if read(&today).lines().count() == 2 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    recorder.kill().unwrap();
+    recorder.wait().unwrap();
+
+    let mut files: Vec<_> = ["forsen", "nymn"]
+        .iter()
+        .flat_map(|channel| fs::read_dir(dir.join(channel)).unwrap())
+        .map(|f| {
+            let path = f.unwrap().path();
+            path.strip_prefix(&dir).unwrap().display().to_string()
+        })
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "forsen/2022-03-13.irc.gz",
+            "forsen/2022-03-14.irc.gz",
+            "forsen/2022-03-14.jsonl.gz",
+            "forsen/2022-03-15.irc",
+            "forsen/2022-03-15.jsonl",
+            "nymn/2022-03-14.irc",
+            "nymn/2022-03-14.jsonl",
+        ]
+    );
+    assert_eq!(
+        gunzip(&forsen.join("2022-03-14.irc.gz")),
+        "compressed by an earlier run\n@badge-info=;badges=;color=#00FF7F;display-name=Tester;tmi-sent-ts=1647302395000 :tester!tester@tester.tmi.twitch.tv PRIVMSG #forsen :last one before midnight\n"
+    );
+    assert_eq!(
+        gunzip(&forsen.join("2022-03-13.irc.gz")),
+        "left by an earlier run\n"
+    );
+    assert_eq!(
+        read(&dir.join("nymn/2022-03-14.jsonl")),
+        concat!(
+            r#"{"channel":"nymn","kind":"usernotice","login":"lurker","text":"still here","tags":{"badge-info":"subscriber/3","badges":"subscriber/3","color":"","display-name":"Lurker","login":"lurker","msg-id":"resub","system-msg":"Lurker subscribed for 3 months.","tmi-sent-ts":"1647302396000"},"sent_at":"2022-03-14T23:59:56Z"}"#,
+            "\n",
+            r#"{"channel":"nymn","kind":"clearchat","login":"spammer","text":null,"tags":{"ban-duration":"600","room-id":"62300805","target-user-id":"12345","tmi-sent-ts":"1647302397000"},"sent_at":"2022-03-14T23:59:57Z"}"#,
+            "\n",
+            r#"{"channel":"nymn","kind":"clearmsg","login":"spammer","text":"buy followers","tags":{"login":"spammer","target-msg-id":"abc-123","tmi-sent-ts":"1647302398000"},"sent_at":"2022-03-14T23:59:58Z"}"#,
+            "\n",
+        )
+    );
+    assert_eq!(read(&dir.join("nymn/2022-03-14.irc")).lines().count(), 3);
+    assert_eq!(
+        read(&today),
+        concat!(
+            r##"{"channel":"forsen","kind":"privmsg","login":"fossabot","text":"Good morning!","tags":{"badge-info":"","badges":"moderator/1","color":"#FF4500","display-name":"Fossabot","tmi-sent-ts":"1647302401000"},"sent_at":"2022-03-15T00:00:01Z"}"##,
+            "\n",
+            r#"{"channel":"forsen","kind":"clearchat","login":null,"text":null,"tags":{"room-id":"22484632","tmi-sent-ts":"1647302402000"},"sent_at":"2022-03-15T00:00:02Z"}"#,
+            "\n",
+        )
+    );
+    let joins = sandbox.irc.received();
+    assert!(
+        joins.contains(&String::from("JOIN #forsen,#nymn")),
+        "{joins:?}"
+    );
+}
+
+#[test]
+fn chat_record_format() {
+    Sandbox::new()
+        .run(&["--format", "{text}", "chat", "record", "forsen"])
+        .assert_snapshot("chat_record_format");
+}
+
+/// A sandbox logged in as `tester` with a token that has `scope`, `user:write:chat` to chat
+/// through Helix or `chat:edit` to chat over IRC.
+fn chatter(scope: &str) -> Sandbox {
+    let sandbox = Sandbox::with_config(&format!(
+        r#"
+[profiles.default]
+login = "tester"
+user_id = "123"
+client_id = "testclientid"
+access_token = "testtoken"
+scopes = ["{scope}"]
+"#
+    ));
+    sandbox.server.mount(Route::new(
+        "GET /oauth2/validate",
+        200,
+        &format!(
+            r#"{{"client_id":"testclientid","login":"tester","scopes":["{scope}"],"user_id":"123","expires_in":14000}}"#
+        ),
+    ));
+    sandbox
+}
+
+/// Stops `tester` from being a moderator of forsen, who is exempt from the limits of chat.
+fn demote(sandbox: &Sandbox) {
+    sandbox.server.mount(Route::new(
+        "GET /ivr/twitch/modvip/forsen",
+        200,
+        r#"{"mods":[],"vips":[]}"#,
+    ));
+}
+
+#[test]
+fn chat_say() {
+    let sandbox = chatter("user:write:chat");
+    demote(&sandbox);
+    sandbox
+        .run(&["chat", "say", "forsen", "hello", "chat"])
+        .assert_snapshot("chat_say");
+    sandbox
+        .run(&["chat", "say", "forsen", "hello", "chat"])
+        .assert_snapshot("chat_say_duplicate");
+    let posts = sandbox
+        .server
+        .requests()
+        .into_iter()
+        .filter(|r| r.starts_with("POST /helix/chat/messages"))
+        .count();
+    assert_eq!(posts, 1);
+}
+
+#[test]
+fn chat_say_duplicate_as_moderator() {
+    let sandbox = chatter("user:write:chat");
+    for _ in 0..2 {
+        let run = sandbox.run(&["chat", "say", "forsen", "hello"]);
+        assert_eq!(run.code, 0, "{}", run.stderr);
+    }
+}
+
+#[test]
+fn chat_reply_json() {
+    chatter("user:write:chat")
+        .run(&[
+            "--output", "json", "chat", "reply", "forsen", "abc-123", "thanks!",
+        ])
+        .assert_snapshot("chat_reply_json");
+}
+
+#[test]
+fn chat_say_dropped() {
+    let sandbox = chatter("user:write:chat");
+    sandbox.server.mount(Route::fixture(
+        "POST /helix/chat/messages",
+        "helix/dropped.json",
+    ));
+    sandbox
+        .run(&["chat", "say", "forsen", "hello"])
+        .assert_snapshot("chat_say_dropped");
+}
+
+#[test]
+fn chat_say_dry_run() {
+    let sandbox = chatter("user:write:chat");
+    sandbox
+        .run(&["--dry-run", "chat", "reply", "forsen", "abc-123", "hello"])
+        .assert_snapshot("chat_say_dry_run");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn chat_say_too_long_dry_run() {
+    let sandbox = chatter("user:write:chat");
+    let message = "a".repeat(501);
+    let run = sandbox.run(&["--dry-run", "chat", "say", "forsen", &message]);
+    assert_eq!(run.code, 2, "{}", run.stderr);
+    assert_eq!(run.stdout, "");
+    assert!(
+        run.stderr.contains("Twitch takes at most 500"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn chat_say_rate_limited() {
+    let sandbox = chatter("user:write:chat");
+    demote(&sandbox);
+    for n in 0..20 {
+        let run = sandbox.run(&["chat", "say", "forsen", &format!("message {n}")]);
+        assert_eq!(run.code, 0, "{}", run.stderr);
+    }
+    let run = sandbox.run(&["chat", "say", "forsen", "one too many"]);
+    assert_eq!(run.code, 6, "{}", run.stderr);
+    assert!(
+        run.stderr.contains("Twitch chat is rate limiting requests"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn chat_say_without_scope() {
+    Sandbox::logged_in()
+        .run(&["chat", "say", "forsen", "hello"])
+        .assert_snapshot("chat_say_without_scope");
+}
+
+#[test]
+fn chat_say_irc() {
+    let sandbox = chatter("chat:edit");
+    sandbox.irc.play_fixture("irc/say.irc");
+    sandbox
+        .run(&["chat", "reply", "#Forsen", "abc-123", "over", "IRC"])
+        .assert_snapshot("chat_say_irc");
+    let received = sandbox.irc.received();
+    assert!(received.contains(&String::from("PASS oauth:testtoken")));
+    assert!(received.contains(&String::from("NICK tester")));
+    assert!(
+        received.contains(&String::from(
+            "@reply-parent-msg-id=abc-123 PRIVMSG #forsen :over IRC"
+        )),
+        "{received:?}"
+    );
+}
+
+#[test]
+fn chat_reply_irc_escapes_id() {
+    let sandbox = chatter("chat:edit");
+    sandbox.irc.play_fixture("irc/say.irc");
+    let run = sandbox.run(&["chat", "reply", "forsen", "abc 123;x", "over", "IRC"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let received = sandbox.irc.received();
+    assert!(
+        received.contains(&String::from(
+            r"@reply-parent-msg-id=abc\s123\:x PRIVMSG #forsen :over IRC"
+        )),
+        "{received:?}"
+    );
+}
+
+#[test]
+fn chat_say_irc_line_break() {
+    let sandbox = chatter("chat:edit");
+    let run = sandbox.run(&["chat", "say", "forsen", "hi\r\nPRIVMSG #forsen :spam"]);
+    assert_eq!(run.code, 2, "{}", run.stderr);
+    assert!(run.stderr.contains("line breaks"), "{}", run.stderr);
+    assert!(sandbox.irc.received().is_empty());
+}
+
+#[test]
+fn chat_say_irc_slow_mode() {
+    let sandbox = chatter("chat:edit");
+    sandbox.irc.play_fixture("irc/say_slow_mode.irc");
+    sandbox
+        .run(&["chat", "say", "forsen", "hello"])
+        .assert_snapshot("chat_say_irc_slow_mode");
+}
+
+#[test]
+fn vods() {
+    Sandbox::logged_in()
+        .run(&["vods", "forsen", "2"])
+        .assert_snapshot("vods");
+}
+
+#[test]
+fn vods_jsonl() {
+    Sandbox::logged_in()
+        .run(&["--output", "jsonl", "vods", "forsen", "2"])
+        .assert_snapshot("vods_jsonl");
+}
+
+#[test]
+fn vods_banned_user() {
+    Sandbox::logged_in()
+        .run(&["vods", "banned_streamer"])
+        .assert_snapshot("vods_banned_user");
+}
+
+#[test]
+fn vods_unknown_user() {
+    Sandbox::logged_in()
+        .run(&["vods", "nobody_here"])
+        .assert_snapshot("vods_unknown_user");
+}
+
+#[test]
+fn live_followed_json() {
+    let sandbox = Sandbox::logged_in();
+    sandbox
+        .run(&["--output", "json", "ll"])
+        .assert_snapshot("live_followed_json");
+    assert_eq!(
+        sandbox.server.requests(),
+        [
+            "GET /oauth2/validate",
+            "GET /helix/streams/followed?user_id=123&first=100",
+            "GET /helix/streams/followed?user_id=123&first=100&after=page2",
+        ]
+    );
+}
+
+#[test]
+fn live_followed_encodes_cursor() {
+    let sandbox = Sandbox::logged_in();
+    let first_page =
+        read(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/helix/followed_1.json"))
+            .replace(r#""cursor": "page2""#, r#""cursor": "eyJi+Ijp/fQ==""#);
+    sandbox.server.mount(Route::new(
+        "GET /helix/streams/followed?user_id=123&first=100",
+        200,
+        &first_page,
+    ));
+    sandbox.server.mount(Route::fixture(
+        "GET /helix/streams/followed?user_id=123&first=100&after=eyJi%2BIjp%2FfQ%3D%3D",
+        "helix/followed_2.json",
+    ));
+    let run = sandbox.run(&["ll"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert_eq!(
+        sandbox.server.requests().last().unwrap(),
+        "GET /helix/streams/followed?user_id=123&first=100&after=eyJi%2BIjp%2FfQ%3D%3D"
+    );
+}
+
+#[test]
+fn live_followed_template() {
+    Sandbox::logged_in()
+        .run(&["--format", "{user_name} playing {game_name}", "ll"])
+        .assert_snapshot("live_followed_template");
+}
+
+#[test]
+fn expired_token() {
+    Sandbox::expired()
+        .run(&["ll"])
+        .assert_snapshot("expired_token");
+}
+
+#[cfg(unix)]
+#[test]
+fn secrets_file_is_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let sandbox = Sandbox::logged_in();
+    let run = sandbox.run(&["ll"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let secrets = fs::metadata(sandbox.path("config/twitch-lookup/secrets.enc")).unwrap();
+    assert_eq!(secrets.permissions().mode() & 0o777, 0o600);
+    // the file is replaced through a temporary file that does not stay behind
+    let leftovers: Vec<_> = fs::read_dir(sandbox.path("config/twitch-lookup"))
+        .unwrap()
+        .flatten()
+        .filter(|e| e.file_name().to_string_lossy().ends_with(".tmp"))
+        .collect();
+    assert!(leftovers.is_empty(), "{leftovers:?}");
+}
+
+#[test]
+fn secrets_file_corrupted_nonce() {
+    let sandbox = Sandbox::logged_in();
+    assert_eq!(sandbox.run(&["ll"]).code, 0);
+    let path = sandbox.path("config/twitch-lookup/secrets.enc");
+    let mut sealed: serde_json::Value = serde_json::from_str(&read(&path)).unwrap();
+    sealed["nonce"] = "AAAA".into();
+    fs::write(&path, sealed.to_string()).unwrap();
+    let run = sandbox.run(&["auth", "status"]);
+    assert_eq!(run.code, 1, "{}", run.stderr);
+    assert!(
+        run.stderr.contains("secrets.enc is corrupted"),
+        "{}",
+        run.stderr
+    );
+}
+
+#[test]
+fn refresh_unavailable_keeps_credentials() {
+    let sandbox = Sandbox::expired();
+    sandbox
+        .server
+        .mount(Route::new("POST /oauth2/token", 503, "unavailable"));
+    let first = sandbox.run(&["ll"]);
+    assert_eq!(first.code, 8, "{}", first.stderr);
+    sandbox.run(&["ll"]);
+    // the second run still has a refresh token to try
+    assert_eq!(
+        sandbox.server.requests(),
+        ["POST /oauth2/token", "POST /oauth2/token"]
+    );
+}
+
+/// Answers the device flow with a code to enter, and a token as soon as it is polled for.
+fn device_flow(sandbox: &Sandbox) {
+    sandbox.server.mount(Route::new(
+        "POST /oauth2/device",
+        200,
+        r#"{"device_code":"devicecode","user_code":"ABCD-EFGH","verification_uri":"https://www.twitch.tv/activate","expires_in":1800,"interval":1}"#,
+    ));
+    sandbox.server.mount(Route::new(
+        "POST /oauth2/token",
+        200,
+        r#"{"access_token":"newtoken","refresh_token":"newrefresh","scope":["user:read:follows"]}"#,
+    ));
+}
+
+#[test]
+fn auth_login_device() {
+    let sandbox = Sandbox::new();
+    device_flow(&sandbox);
+    sandbox
+        .run(&["auth", "login"])
+        .assert_snapshot("auth_login_device");
+    assert_eq!(
+        sandbox.server.requests(),
+        [
+            "POST /oauth2/device",
+            "POST /oauth2/token",
+            "GET /oauth2/validate"
+        ]
+    );
+    // the token is there for the next command
+    let run = sandbox.run(&["ll"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+}
+
+#[test]
+fn auth_status() {
+    let run = Sandbox::logged_in().run(&["auth", "status"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    // the expiry is counted from now, the other lines are fixed
+    let lines: Vec<&str> = run.stdout.lines().collect();
+    assert_eq!(lines[0], "Logged in as tester (123) on profile default");
+    assert_eq!(
+        lines[1],
+        "- Scopes: user:read:follows, moderator:read:chatters"
+    );
+    assert!(lines[2].starts_with("- Expires: "), "{}", run.stdout);
+    assert_eq!(lines[3], "- Token: valid");
+}
+
+#[test]
+fn auth_status_rejected() {
+    let sandbox = Sandbox::logged_in();
+    sandbox.server.mount(Route::new(
+        "GET /oauth2/validate",
+        401,
+        r#"{"status":401,"message":"invalid access token"}"#,
+    ));
+    let run = sandbox.run(&["auth", "status"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert!(
+        run.stdout
+            .ends_with("- Token: rejected by Twitch, run `tl auth login`\n"),
+        "{}",
+        run.stdout
+    );
+}
+
+#[test]
+fn auth_logout_then_status() {
+    let sandbox = Sandbox::logged_in();
+    sandbox
+        .server
+        .mount(Route::new("POST /oauth2/revoke", 200, ""));
+    sandbox
+        .run(&["auth", "logout"])
+        .assert_snapshot("auth_logout");
+    sandbox
+        .run(&["auth", "status"])
+        .assert_snapshot("auth_status_logged_out");
+    // without a token there is nothing left to validate
+    assert_eq!(sandbox.server.requests(), ["POST /oauth2/revoke"]);
+}
+
+#[test]
+fn auth_switch_profile() {
+    let sandbox = Sandbox::with_config(
+        r#"
+[profiles.default]
+login = "tester"
+user_id = "123"
+client_id = "testclientid"
+access_token = "testtoken"
+
+[profiles.other]
+login = "other"
+user_id = "456"
+client_id = "testclientid"
+access_token = "othertoken"
+"#,
+    );
+    sandbox
+        .run(&["auth", "switch", "other"])
+        .assert_snapshot("auth_switch_profile");
+    sandbox
+        .run(&["auth", "profiles"])
+        .assert_snapshot("auth_switch_profiles");
+    // later commands run as the new default
+    let run = sandbox.run(&["--output", "json", "auth", "status"]);
+    let status: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(status["profile"], "other");
+    assert!(sandbox
+        .server
+        .requests()
+        .iter()
+        .all(|r| !r.starts_with("POST")));
+}
+
+#[test]
+fn auth_switch_new_profile_logs_in() {
+    let sandbox = Sandbox::logged_in();
+    device_flow(&sandbox);
+    sandbox
+        .run(&["auth", "switch", "second"])
+        .assert_snapshot("auth_switch_new_profile");
+    assert_eq!(
+        sandbox.server.requests(),
+        [
+            "POST /oauth2/device",
+            "POST /oauth2/token",
+            "GET /oauth2/validate"
+        ]
+    );
+}
+
+#[test]
+fn auth_switch_account() {
+    let sandbox = Sandbox::logged_in();
+    device_flow(&sandbox);
+    sandbox
+        .server
+        .mount(Route::new("POST /oauth2/revoke", 200, ""));
+    sandbox
+        .run(&["auth", "switch"])
+        .assert_snapshot("auth_switch_account");
+    assert_eq!(
+        sandbox.server.requests(),
+        [
+            "POST /oauth2/revoke",
+            "POST /oauth2/device",
+            "POST /oauth2/token",
+            "GET /oauth2/validate"
+        ]
+    );
+}
+
+#[test]
+fn login_with_rejected_token() {
+    let sandbox = Sandbox::new();
+    sandbox.server.mount(Route::new(
+        "GET /oauth2/validate",
+        401,
+        r#"{"status":401,"message":"invalid access token"}"#,
+    ));
+    let run = sandbox.run(&["auth", "login", "--token", "badtoken"]);
+    assert_eq!(run.code, 5, "{}", run.stderr);
+}
+
+/// Runs `tl auth login` with the redirect flow, sends the browser back to it with the query
+/// `make_query` builds from the expected state, and returns the page the browser was shown
+/// along with the exit code.
+fn redirect_login(make_query: impl FnOnce(&str) -> String) -> (String, i32) {
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let sandbox = Sandbox::with_auth(
+        &format!("flow = \"redirect\"\nclient_secret = \"testsecret\"\nredirect_port = {port}"),
+        "",
+    );
+    sandbox.server.mount(Route::new(
+        "POST /oauth2/token",
+        200,
+        r#"{"access_token":"newtoken","refresh_token":"newrefresh","scope":[]}"#,
+    ));
+    let mut tl = sandbox.spawn_interactive(&["auth", "login"]);
+    let mut stderr = BufReader::new(tl.stderr.take().unwrap());
+    let mut line = String::new();
+    while !line.contains("state=") {
+        line.clear();
+        assert_ne!(
+            stderr.read_line(&mut line).unwrap(),
+            0,
+            "tl exited before printing the login link"
+        );
+    }
+    let state = line
+        .split("state=")
+        .nth(1)
+        .unwrap()
+        .split('&')
+        .next()
+        .unwrap();
+    let mut browser = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    write!(
+        browser,
+        "GET /?{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        make_query(state.trim())
+    )
+    .unwrap();
+    let mut page = String::new();
+    browser.read_to_string(&mut page).unwrap();
+    let code = tl.wait().unwrap().code().unwrap_or(-1);
+    (page, code)
+}
+
+#[test]
+fn redirect_login_succeeds() {
+    let (page, code) = redirect_login(|state| format!("code=testcode&state={state}"));
+    assert!(page.starts_with("HTTP/1.1 200"), "{page}");
+    assert!(page.contains("You are logged in"), "{page}");
+    assert_eq!(code, 0);
+}
+
+#[test]
+fn redirect_login_forged_state() {
+    let (page, code) = redirect_login(|_| String::from("code=testcode&state=forged"));
+    assert!(page.starts_with("HTTP/1.1 400"), "{page}");
+    assert!(page.contains("unexpected state"), "{page}");
+    assert_eq!(code, 5);
+}
+
+#[test]
+fn redirect_login_denied() {
+    let (page, code) = redirect_login(|state| {
+        format!("error=access_denied&error_description=%3Cb%3Enope%3C%2Fb%3E&state={state}")
+    });
+    assert!(page.starts_with("HTTP/1.1 400"), "{page}");
+    assert!(
+        page.contains("was denied: &lt;b&gt;nope&lt;/b&gt;"),
+        "{page}"
+    );
+    assert!(!page.contains("You are logged in"), "{page}");
+    assert_eq!(code, 5);
+}
+
+#[test]
+fn missing_scope() {
+    chatter("chat:read")
+        .run(&["chat", "count", "forsen"])
+        .assert_snapshot("missing_scope");
+}
+
+#[test]
+fn rejected_token() {
+    let sandbox = Sandbox::logged_in();
+    sandbox.server.mount(Route::new(
+        "GET /helix/streams/followed?user_id=123&first=100",
+        401,
+        r#"{"error":"Unauthorized","status":401,"message":"Invalid OAuth token"}"#,
+    ));
+    sandbox.run(&["ll"]).assert_snapshot("rejected_token");
+}
+
+// Cache
+
+#[test]
+fn cached_answer() {
+    let sandbox = Sandbox::new();
+    let first = sandbox.run(&["user", "id", "forsen"]);
+    let second = sandbox.run(&["user", "id", "forsen"]);
+    assert_eq!(first.stdout, second.stdout);
+    assert_eq!(sandbox.server.requests(), ["GET /ivr/twitch/user/forsen"]);
+}
+
+#[test]
+fn cached_per_account() {
+    let sandbox = Sandbox::with_config(
+        r#"
+[profiles.default]
+login = "tester"
+user_id = "123"
+client_id = "testclientid"
+access_token = "testtoken"
+
+[profiles.other]
+login = "other"
+user_id = "456"
+client_id = "testclientid"
+access_token = "othertoken"
+"#,
+    );
+    for profile in ["default", "other", "default"] {
+        let run = sandbox.run(&["--profile", profile, "vods", "forsen", "2"]);
+        assert_eq!(run.code, 0, "{}", run.stderr);
+    }
+    let videos = sandbox
+        .server
+        .requests()
+        .into_iter()
+        .filter(|r| r.starts_with("GET /helix/videos"))
+        .count();
+    assert_eq!(videos, 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn cache_entries_are_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let sandbox = Sandbox::logged_in();
+    let run = sandbox.run(&["ll"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let entries: Vec<_> = fs::read_dir(sandbox.path("cache/twitch-lookup"))
+        .unwrap()
+        .flatten()
+        .collect();
+    assert!(!entries.is_empty());
+    for entry in entries {
+        let name = entry.file_name();
+        assert!(name.to_string_lossy().ends_with(".json"), "{name:?}");
+        let mode = entry.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600, "{name:?}");
+    }
+}
+
+#[test]
+fn offline_marks_age() {
+    let sandbox = Sandbox::new();
+    sandbox.run(&["user", "compact", "forsen"]);
+    let run = sandbox.run(&["--offline", "user", "compact", "forsen"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let lines: Vec<&str> = run
+        .stdout
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    assert!(lines.len() > 1, "{}", run.stdout);
+    for line in lines {
+        assert!(
+            line.ends_with(" ago)") && line.contains("(cached "),
+            "{line}"
+        );
+    }
+    assert_eq!(sandbox.server.requests(), ["GET /ivr/twitch/user/forsen"]);
+}
+
+#[test]
+fn offline_marks_age_json() {
+    let sandbox = Sandbox::new();
+    sandbox.run(&["user", "compact", "forsen"]);
+    let run = sandbox.run(&["--offline", "--output", "json", "user", "compact", "forsen"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    let user: serde_json::Value = serde_json::from_str(&run.stdout).unwrap();
+    assert_eq!(user["login"], "forsen");
+    assert!(user["cached_at"].is_string(), "{user}");
+    assert!(
+        user["age_secs"].as_i64().is_some_and(|age| age < 60),
+        "{user}"
+    );
+}
+
+#[test]
+fn offline_miss() {
+    let sandbox = Sandbox::new();
+    sandbox
+        .run(&["--offline", "title", "forsen"])
+        .assert_snapshot("offline_miss");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+// Recording
+
+#[test]
+fn record_and_replay() {
+    let sandbox = Sandbox::logged_in();
+    let dir = sandbox.path("recording");
+    let dir = dir.to_str().unwrap();
+    let recorded = sandbox.run(&["--record", dir, "vods", "forsen", "2"]);
+    assert_eq!(recorded.code, 0, "{}", recorded.stderr);
+    let requests = sandbox.server.requests();
+
+    let files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|f| f.unwrap().file_name().into_string().unwrap())
+        .collect();
+    assert_eq!(files.len(), 2);
+    for file in &files {
+        let exchange = std::fs::read_to_string(sandbox.path("recording").join(file)).unwrap();
+        assert!(!exchange.contains("testtoken"), "{file} leaks the token");
+    }
+
+    let replayed = sandbox.run(&["--replay", dir, "vods", "forsen", "2"]);
+    assert_eq!(replayed.code, 0, "{}", replayed.stderr);
+    assert_eq!(recorded.stdout, replayed.stdout);
+    assert_eq!(sandbox.server.requests(), requests);
+}
+
+#[test]
+fn replay_not_recorded() {
+    let sandbox = Sandbox::new();
+    let dir = sandbox.path("recording");
+    std::fs::create_dir(&dir).unwrap();
+    sandbox
+        .run(&["--replay", dir.to_str().unwrap(), "title", "forsen"])
+        .assert_snapshot("replay_not_recorded");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+// Tracing
+
+#[test]
+fn dry_run() {
+    let sandbox = Sandbox::logged_in();
+    sandbox.run(&["--dry-run", "ll"]).assert_snapshot("dry_run");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn dry_run_follows_up() {
+    let sandbox = Sandbox::logged_in();
+    sandbox.run(&["user", "id", "forsen"]);
+    sandbox
+        .run(&["--dry-run", "vods", "forsen"])
+        .assert_snapshot("dry_run_follows_up");
+    assert_eq!(sandbox.server.requests(), ["GET /ivr/twitch/user/forsen"]);
+}
+
+#[test]
+fn dry_run_stubs_answers() {
+    let sandbox = Sandbox::logged_in();
+    sandbox
+        .run(&["--dry-run", "vods", "forsen"])
+        .assert_snapshot("dry_run_stubs_answers");
+    assert!(sandbox.server.requests().is_empty());
+}
+
+#[test]
+fn verbose_traces_requests() {
+    let run = Sandbox::new().run(&["-v", "title", "forsen"]);
+    assert_eq!(run.code, 0, "{}", run.stderr);
+    assert!(
+        run.stderr.contains(r#"service="DecAPI" method="GET" url=http://mock/decapi/twitch/status/forsen cache="miss" retries=0 status=200"#),
+        "{}",
+        run.stderr
+    );
+}
@@ -0,0 +1,492 @@
+//! A local stand-in for every service `tl` talks to, serving the recorded responses in
+//! `tests/fixtures`, and a sandbox that runs the `tl` binary against it.
+//!
+//! Chat is played from `tests/fixtures/irc/*.irc`, one line per IRC message with a blank
+//! line where the server drops the connection and the next one picks up. A line like
+//! `< PRIVMSG` holds the chat back until the client sent that command.
+//!
+//! Outputs are compared with the snapshots in `tests/snapshots`, run the tests with
+//! `UPDATE_SNAPSHOTS=1` to rewrite them after an intended change.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tempfile::TempDir;
+use tungstenite::Message;
+
+/// What the stand-in shows instead of its address, which changes from run to run.
+const SERVER: &str = "http://mock";
+const IRC_SERVER: &str = "ws://mock";
+
+/// A canned response to a request line like `GET /v2/twitch/user/forsen`.
+#[derive(Clone)]
+pub struct Route {
+    request: String,
+    status: u16,
+    body: String,
+}
+
+impl Route {
+    pub fn new(request: &str, status: u16, body: &str) -> Self {
+        Self {
+            request: request.to_string(),
+            status,
+            body: body.to_string(),
+        }
+    }
+
+    /// A 200 with the contents of `tests/fixtures/<fixture>`.
+    pub fn fixture(request: &str, fixture: &str) -> Self {
+        let path = manifest_dir().join("tests/fixtures").join(fixture);
+        let body = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("could not read {}: {e}", path.display()));
+        Self::new(request, 200, &body)
+    }
+}
+
+/// The responses every test starts with, anything else is answered with a 404.
+fn routes() -> Vec<Route> {
+    vec![
+        // ivr.fi
+        Route::fixture("GET /ivr/twitch/user/forsen", "ivr/forsen.json"),
+        Route::fixture(
+            "GET /ivr/twitch/user/banned_streamer",
+            "ivr/banned_streamer.json",
+        ),
+        Route::fixture("GET /ivr/twitch/modvip/forsen", "ivr/modvip_forsen.json"),
+        Route::new(
+            "GET /ivr/twitch/user/nobody_here",
+            404,
+            r#"{"statusCode":404,"error":"Not Found","message":"User was not found"}"#,
+        ),
+        // logs.ivr.fi
+        Route::new(
+            "GET /logs/list?channel=forsen&user=tester",
+            200,
+            r#"{"availableLogs":[{"year":"2022","month":"3"}]}"#,
+        ),
+        // DecAPI
+        Route::new(
+            "GET /decapi/twitch/followage/forsen/tester",
+            200,
+            "3 years, 2 months, 5 days",
+        ),
+        Route::new(
+            "GET /decapi/twitch/followage/forsen/lurker",
+            200,
+            "lurker does not follow forsen",
+        ),
+        Route::new(
+            "GET /decapi/twitch/followage/forsen/nobody_here",
+            200,
+            "User not found: nobody_here",
+        ),
+        Route::new("GET /decapi/twitch/status/forsen", 200, "Elden Ring"),
+        Route::new("GET /decapi/twitch/viewercount/forsen", 200, "24011"),
+        Route::new(
+            "GET /decapi/twitch/viewercount/nymn",
+            200,
+            "nymn is offline",
+        ),
+        // Helix
+        Route::fixture(
+            "GET /helix/chat/chatters?broadcaster_id=22484632&moderator_id=123&first=1000",
+            "helix/chatters.json",
+        ),
+        Route::fixture("POST /helix/chat/messages", "helix/sent.json"),
+        Route::fixture(
+            "GET /helix/videos?user_id=22484632&first=2",
+            "helix/videos.json",
+        ),
+        Route::fixture(
+            "GET /helix/streams/followed?user_id=123&first=100",
+            "helix/followed_1.json",
+        ),
+        Route::fixture(
+            "GET /helix/streams/followed?user_id=123&first=100&after=page2",
+            "helix/followed_2.json",
+        ),
+        // Twitch OAuth2
+        Route::fixture("GET /oauth2/validate", "oauth2/validate.json"),
+        Route::new(
+            "POST /oauth2/token",
+            400,
+            r#"{"status":400,"message":"Invalid refresh token"}"#,
+        ),
+    ]
+}
+
+/// An HTTP/1.1 server on a random local port answering from a route table.
+pub struct MockServer {
+    url: String,
+    routes: Arc<Mutex<Vec<Route>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind the mock server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = Arc::new(Mutex::new(routes()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (table, log) = (Arc::clone(&routes), Arc::clone(&requests));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let _ = serve(stream, &table, &log);
+            }
+        });
+        Self {
+            url,
+            routes,
+            requests,
+        }
+    }
+
+    /// Answers `route`'s request with it from now on, in place of the recorded response.
+    pub fn mount(&self, route: Route) {
+        let mut routes = self.routes.lock().unwrap();
+        routes.retain(|r| r.request != route.request);
+        routes.push(route);
+    }
+
+    /// The request lines received so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn serve(
+    mut stream: TcpStream,
+    routes: &Mutex<Vec<Route>>,
+    log: &Mutex<Vec<String>>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    // form bodies are not looked at, but have to be read before answering
+    reader.read_exact(&mut vec![0; content_length])?;
+
+    let request = request_line
+        .split_whitespace()
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(" ");
+    log.lock().unwrap().push(request.clone());
+    let route = routes
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|r| r.request == request)
+        .cloned()
+        .unwrap_or_else(|| Route::new(&request, 404, r#"{"error":"Not Found","status":404}"#));
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        route.status,
+        reason(route.status),
+        route.body.len(),
+        route.body
+    )?;
+    stream.flush()
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+/// A Twitch IRC server on a WebSocket that plays a recorded chat to whoever joins.
+pub struct IrcServer {
+    url: String,
+    script: Arc<Mutex<Vec<Vec<String>>>>,
+    received: Arc<Mutex<Vec<String>>>,
+}
+
+impl IrcServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind the IRC server");
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let script = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let (sessions, log) = (Arc::clone(&script), Arc::clone(&received));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let session = {
+                    let mut sessions = sessions.lock().unwrap();
+                    (!sessions.is_empty()).then(|| sessions.remove(0))
+                };
+                let _ = play(stream, session.unwrap_or_default(), &log);
+            }
+        });
+        let server = Self {
+            url,
+            script,
+            received,
+        };
+        server.play_fixture("irc/forsen.irc");
+        server
+    }
+
+    /// Plays `tests/fixtures/<fixture>` to the next connections instead of the recorded chat.
+    pub fn play_fixture(&self, fixture: &str) {
+        let path = manifest_dir().join("tests/fixtures").join(fixture);
+        let chat = fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("could not read {}: {e}", path.display()));
+        *self.script.lock().unwrap() = chat
+            .split("\n\n")
+            .map(|session| session.lines().map(String::from).collect())
+            .collect();
+    }
+
+    /// The lines clients sent so far, in order.
+    pub fn received(&self) -> Vec<String> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+/// Waits for the client to join, then sends `session` and hangs up at its end. A `PING`
+/// is only followed by the next line once the client answered it.
+fn play(stream: TcpStream, session: Vec<String>, log: &Mutex<Vec<String>>) -> io::Result<()> {
+    let mut socket = tungstenite::accept(stream).map_err(io::Error::other)?;
+    let receive = |socket: &mut tungstenite::WebSocket<TcpStream>, until: &str| loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let mut done = false;
+                for line in text.lines() {
+                    // commands are matched after the tags of a tagged line
+                    let command = match line.strip_prefix('@') {
+                        Some(tagged) => tagged.split_once(' ').map_or("", |(_, rest)| rest),
+                        None => line,
+                    };
+                    done |= command.starts_with(until);
+                    log.lock().unwrap().push(line.to_string());
+                }
+                if done {
+                    return Ok(());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return Err(io::Error::other(e)),
+        }
+    };
+    receive(&mut socket, "JOIN ")?;
+    for line in session {
+        if let Some(command) = line.strip_prefix("< ") {
+            receive(&mut socket, command)?;
+            continue;
+        }
+        socket
+            .send(Message::Text(line.clone()))
+            .map_err(io::Error::other)?;
+        if line.starts_with("PING ") {
+            receive(&mut socket, "PONG ")?;
+        }
+    }
+    socket.close(None).map_err(io::Error::other)?;
+    // the client may still be reading, give it the chance to take the close
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+/// A config and cache directory of its own, pointed at a fresh [`MockServer`] and [`IrcServer`].
+pub struct Sandbox {
+    pub server: MockServer,
+    pub irc: IrcServer,
+    home: TempDir,
+}
+
+impl Sandbox {
+    /// A sandbox without an account.
+    pub fn new() -> Self {
+        Self::with_config("")
+    }
+
+    /// A sandbox logged in as `tester` (user ID 123) with a token that still has to be validated.
+    pub fn logged_in() -> Self {
+        Self::with_config(
+            r#"
+[profiles.default]
+login = "tester"
+user_id = "123"
+client_id = "testclientid"
+access_token = "testtoken"
+scopes = ["user:read:follows", "moderator:read:chatters"]
+"#,
+        )
+    }
+
+    /// A sandbox logged in with a token that expired, and a refresh token Twitch no longer takes.
+    pub fn expired() -> Self {
+        Self::with_config(
+            r#"
+[profiles.default]
+login = "tester"
+user_id = "123"
+client_id = "testclientid"
+access_token = "expiredtoken"
+refresh_token = "staletoken"
+scopes = ["user:read:follows", "moderator:read:chatters"]
+expires_at = "2022-03-01T00:00:00Z"
+"#,
+        )
+    }
+
+    /// A sandbox with `extra` appended to the config file, e.g. a profile or a `[chat]` section.
+    pub fn with_config(extra: &str) -> Self {
+        Self::with_auth("", extra)
+    }
+
+    /// A sandbox with `auth` added to the `[auth]` section of the config file and `extra`
+    /// appended to it.
+    pub fn with_auth(auth: &str, extra: &str) -> Self {
+        let server = MockServer::start();
+        let irc = IrcServer::start();
+        let home = tempfile::tempdir().expect("could not create the sandbox");
+        let url = &server.url;
+        let irc_url = &irc.url;
+        // tokens in the config file are moved into the encrypted secrets file on the first run
+        let config = format!(
+            r#"client_id = "testclientid"
+secret_store = "file"
+
+[auth]
+url = "{url}/oauth2"
+{auth}
+
+[endpoints]
+helix = "{url}/helix"
+ivr = "{url}/ivr"
+logs = "{url}/logs"
+decapi = "{url}/decapi"
+irc = "{irc_url}"
+
+[http]
+retries = 0
+{extra}"#
+        );
+        let dir = home.path().join("config/twitch-lookup");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("twitch-lookup.toml"), config).unwrap();
+        Self { server, irc, home }
+    }
+
+    /// Runs `tl` with `args` and waits for it to exit.
+    pub fn run(&self, args: &[&str]) -> Run {
+        let output = self.command(args).output().expect("could not run tl");
+        let home = self.home.path().to_string_lossy();
+        let normalize = |text: &str| {
+            text.replace(&self.server.url, SERVER)
+                .replace(&self.irc.url, IRC_SERVER)
+                .replace(&*home, "~")
+        };
+        Run {
+            args: normalize(&args.join(" ")),
+            code: output.status.code().unwrap_or(-1),
+            stdout: normalize(&String::from_utf8_lossy(&output.stdout)),
+            stderr: normalize(&String::from_utf8_lossy(&output.stderr)),
+        }
+    }
+
+    /// Starts `tl` with `args` for commands that run until they are stopped.
+    pub fn spawn(&self, args: &[&str]) -> Child {
+        self.command(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("could not start tl")
+    }
+
+    /// Starts `tl` with `args` with its stderr piped, for commands that wait on the user.
+    pub fn spawn_interactive(&self, args: &[&str]) -> Child {
+        self.command(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .expect("could not start tl")
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let home = self.home.path();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_tl"));
+        command
+            .args(["--color", "never"])
+            .args(args)
+            .env_clear()
+            .env("PATH", std::env::var_os("PATH").unwrap_or_default())
+            .env("HOME", home)
+            .env("XDG_CONFIG_HOME", home.join("config"))
+            .env("XDG_CACHE_HOME", home.join("cache"))
+            .env("TL_PASSPHRASE", "correct horse battery staple")
+            // links count as opened without starting anything
+            .env("BROWSER", "true")
+            // chat timestamps are shown in local time
+            .env("TZ", "UTC");
+        command
+    }
+
+    /// A path inside the sandbox, shown as `~/<name>` in snapshots.
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.home.path().join(name)
+    }
+}
+
+/// The outcome of one `tl` invocation.
+pub struct Run {
+    args: String,
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Run {
+    /// Compares the exit code, stdout and stderr with `tests/snapshots/<name>.snap`.
+    #[track_caller]
+    pub fn assert_snapshot(&self, name: &str) {
+        let actual = format!(
+            "$ tl {}\nexit: {}\n--- stdout\n{}--- stderr\n{}",
+            self.args, self.code, self.stdout, self.stderr
+        );
+        let path = manifest_dir()
+            .join("tests/snapshots")
+            .join(format!("{name}.snap"));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::write(&path, &actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+            panic!(
+                "{} is missing, run with UPDATE_SNAPSHOTS=1 to create it\n\n{actual}",
+                path.display()
+            )
+        });
+        assert_eq!(expected, actual, "{name} does not match its snapshot");
+    }
+}
+
+fn manifest_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).to_path_buf()
+}